@@ -1,9 +1,13 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use pprof::criterion::{Output, PProfProfiler};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use devnull_bot::grid::{make_grid, Pos};
+use devnull_bot::grid::{make_grid, Grid, Pos};
+use devnull_bot::map_generator::{self, GeneratorOptions};
 use devnull_bot::mcts;
-use devnull_bot::pathfinding::{FastAggressivePathfinder, Pathfinder, PathfindingGrid};
+use devnull_bot::pathfinding::{FastAggressivePathfinder, Pathfinder, PathfindingBackend,
+                               PathfindingGrid};
 use devnull_bot::search;
 use devnull_bot::simulation;
 use devnull_bot::simulation::Style;
@@ -14,7 +18,6 @@ impl mcts::MCTS for MCTS {
     type Action = search::Action;
     type State = simulation::State;
     type Evaluator = search::ThreatsAreFarEval;
-    type Heuristic = search::ThreatsAreFarEval;
     type Budget = mcts::EvalCallsBudget;
 }
 
@@ -52,9 +55,11 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 black_box(&Some(Pos { x: 9, y: 1 })))
         });
     });
-    c.bench_function("PathfindingGrid 22x15 creation", |b| {
-        b.iter(|| PathfindingGrid::new(black_box(grid.clone())));
-    });
+    bench_pathfinding_backends(c, "22x15", &grid);
+    let large_grid = map_generator::generate_map(
+        &GeneratorOptions { width: 61, height: 61, ..Default::default() })
+        .unwrap().game.grid;
+    bench_pathfinding_backends(c, "61x61", &large_grid);
     c.bench_function("sampling_algorithm 22x15 1000 evals", |b| {
         let game = simulation::Game {
             tick: 1,
@@ -108,6 +113,41 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
+/// Compare pathfinding backends on creation, and on the next move lookups
+/// done by threats (from a fresh grid, and once rows are cached).
+fn bench_pathfinding_backends(c: &mut Criterion, grid_name: &str, grid: &Grid) {
+    const NUM_LOOKUPS: usize = 1000;
+    let mut rng = ChaCha8Rng::seed_from_u64(42);
+    let lookups: Vec<(Pos, Pos)> = (0..NUM_LOOKUPS).map(|_| {
+        let from = grid.empty_tiles[rng.gen_range(0..grid.empty_tiles.len())];
+        let to = grid.empty_tiles[rng.gen_range(0..grid.empty_tiles.len())];
+        (from, to)
+    }).collect();
+    let do_lookups = |pathfinding: &PathfindingGrid| {
+        for (from, to) in &lookups {
+            black_box(pathfinding.get_pathfinding_next_move(from, to));
+            black_box(pathfinding.get_aggressive_next_move(from, to));
+        }
+    };
+    let mut group = c.benchmark_group(format!("PathfindingGrid {}", grid_name));
+    group.sample_size(10);
+    for backend in [PathfindingBackend::Precomputed, PathfindingBackend::Lazy] {
+        let name = format!("{:?}", backend);
+        group.bench_function(BenchmarkId::new("creation", &name), |b| {
+            b.iter(|| PathfindingGrid::with_backend(black_box(grid.clone()), backend));
+        });
+        group.bench_function(BenchmarkId::new("creation + lookups", &name), |b| {
+            b.iter(|| do_lookups(&PathfindingGrid::with_backend(grid.clone(), backend)));
+        });
+        let pathfinding = PathfindingGrid::with_backend(grid.clone(), backend);
+        do_lookups(&pathfinding);
+        group.bench_function(BenchmarkId::new("cached lookups", &name), |b| {
+            b.iter(|| do_lookups(&pathfinding));
+        });
+    }
+    group.finish();
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
@@ -1,3 +1,4 @@
+import os
 import time
 
 from game_message import *
@@ -14,7 +15,20 @@ class Bot:
 
     def on_first_tick(self, game_message: TeamGameState) -> None:
         state = rust_interop.to_rust_game_state(game_message)
-        self.bot = devnull_bot.create_bot(state)
+        # Crash locally on mispredictions to find bugs, but keep playing on
+        # the real servers.
+        recover_desyncs = ("TOKEN" in os.environ
+                           or os.environ.get("RECOVER_DESYNCS") == "1")
+        # Maps next to this file, if shipped, regardless of where we run from.
+        # Otherwise, maps embedded in the wheel (see 'package.sh').
+        maps_dir = os.path.join(os.path.dirname(os.path.abspath(__file__)), "maps")
+        # Keep maps we don't know, to evaluate them later.
+        save_new_map = os.environ.get("SAVE_NEW_MAPS") == "1"
+        # Bot spec to play with (see 'bot_spec.rs'), e.g. 'show-off,budget=150ms'.
+        bot = os.environ.get("DEVNULL_BOT")
+        self.bot = devnull_bot.create_bot(state, recover_desyncs=recover_desyncs,
+                                          maps_dir=maps_dir, save_new_map=save_new_map,
+                                          bot=bot)
         self.initialized = True
 
     def get_next_move(self, game_message: TeamGameState):
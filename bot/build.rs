@@ -0,0 +1,40 @@
+//! With the 'embedded-maps' feature, embed all maps of the 'maps' folder in the
+//! binary, so it can run without shipping the folder (see
+//! 'map_loader::EMBEDDED_MAPS').
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("embedded_maps.rs");
+    let maps_path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("maps");
+    println!("cargo:rerun-if-changed=build.rs");
+    let mut code = String::from("&[\n");
+    if env::var("CARGO_FEATURE_EMBEDDED_MAPS").is_ok() {
+        println!("cargo:rerun-if-changed={}", maps_path.display());
+        let mut map_paths: Vec<_> = fs::read_dir(&maps_path).expect("no maps folder")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_dir())
+            .collect();
+        map_paths.sort();
+        for map_path in map_paths {
+            println!("cargo:rerun-if-changed={}", map_path.display());
+            let name = map_path.file_name().unwrap().to_str().unwrap();
+            let file = |filename: &str, macro_name: &str| {
+                let path = map_path.join(filename);
+                if path.exists() {
+                    format!("Some({}!({:?}))", macro_name, path.display().to_string())
+                } else {
+                    "None".to_string()
+                }
+            };
+            code += &format!(
+                "    EmbeddedMap {{ name: {:?}, layout_png: {}, layout_txt: {}, meta: {} }},\n",
+                name, file("layout.png", "include_bytes"), file("layout.txt", "include_str"),
+                file("meta.json", "include_str"));
+        }
+    }
+    code += "]\n";
+    fs::write(out_path, code).unwrap();
+}
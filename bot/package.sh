@@ -1,5 +1,6 @@
 #!/bin/sh
 set -e
-./venv/bin/maturin build --release
-rm devnull.zip
-zip -r devnull.zip *.py core-requirements.txt requirements.txt ./target/wheels/*.whl maps/
+# Maps are embedded in the wheel, no need to ship the 'maps' folder.
+./venv/bin/maturin build --release --features embedded-maps
+rm -f devnull.zip
+zip -r devnull.zip *.py core-requirements.txt requirements.txt ./target/wheels/*.whl
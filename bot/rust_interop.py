@@ -14,6 +14,7 @@ def to_rust_threat(threat: Threat) -> devnull_bot.GameThreat:
     return devnull_bot.GameThreat(
         position=to_rust_position(threat.position),
         direction=to_rust_direction(threat.direction),
+        personality=threat.personality,
         style=threat.style)
 
 
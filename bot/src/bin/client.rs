@@ -0,0 +1,76 @@
+/// Bot that plays on the game server directly, without the Python client.
+/// Same environment variables as 'application.py' / 'bot.py':
+/// - TOKEN: register with this token (real server), and recover from desyncs.
+/// - SAVE_JSONL_PATH: save each tick to this '.jsonl' file.
+/// - RECOVER_DESYNCS=1: recover from desyncs instead of crashing.
+/// - DEVNULL_MAPS_DIR: load maps from this folder (or use '--maps-dir').
+/// - SAVE_NEW_MAPS=1: save maps we don't know to the maps folder.
+/// - DEVNULL_BOT: bot spec to play with (or use '--bot').
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use devnull_bot::game_client::play_game;
+use devnull_bot::game_message::ClientMessage;
+use devnull_bot::map_loader::{self, find_or_create_map};
+use devnull_bot::bot_spec::BotSpec;
+use devnull_bot::search::{Bot, DesyncHandling, BEST_BOT};
+use devnull_bot::simulation::State;
+
+#[derive(Debug, Parser)]
+#[clap(name = "devnull_bot_client")]
+struct Cli {
+    #[arg(long, default_value = "ws://127.0.0.1:8765", help = "Game server to connect to.")]
+    url: String,
+    #[arg(long, help = "Where maps are. Defaults to '$DEVNULL_MAPS_DIR', or 'maps'.")]
+    maps_dir: Option<PathBuf>,
+    #[arg(long, help = "Bot spec to play with, e.g. 'show-off,budget=150ms'. Defaults to \
+                         '$DEVNULL_BOT', or the best bot.")]
+    bot: Option<BotSpec>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    map_loader::set_maps_dir(cli.maps_dir);
+    let token = std::env::var("TOKEN").ok();
+    // Crash locally on mispredictions to find bugs, but keep playing on the
+    // real servers.
+    let recover_desyncs = token.is_some()
+        || std::env::var("RECOVER_DESYNCS").is_ok_and(|v| v == "1");
+    let save_new_maps = std::env::var("SAVE_NEW_MAPS").is_ok_and(|v| v == "1");
+    let spec = cli.bot.or_else(|| std::env::var("DEVNULL_BOT").ok().map(|spec| {
+        spec.parse().unwrap_or_else(|e| panic!("Invalid DEVNULL_BOT: {}", e))
+    })).unwrap_or_else(|| BotSpec::preset(BEST_BOT));
+    let registration = match token {
+        Some(token) => ClientMessage::Register { team_name: None, token: Some(token) },
+        None => ClientMessage::Register {
+            team_name: Some("MyRustyBot".to_string()), token: None },
+    };
+    let mut recorder = std::env::var("SAVE_JSONL_PATH").ok().map(|path| {
+        println!("Saving game ticks to {}", path);
+        BufWriter::new(File::create(path).expect("Failed to create save file"))
+    });
+
+    let (mut socket, _) = tungstenite::connect(&cli.url).expect("Failed to connect");
+    let make_bot = |game: &_| {
+        let map = find_or_create_map(game, save_new_maps).expect("Failed to load maps");
+        let mut bot = Bot::new(State::new(map.game), /*seed=*/42, spec.clone());
+        if recover_desyncs {
+            bot.desync_handling = DesyncHandling::Resync;
+        }
+        bot
+    };
+    let last_message = play_game(&mut socket, registration, make_bot,
+                                 recorder.as_mut().map(|r| r as &mut dyn Write))
+        .expect("Error while playing");
+    if let Some(mut recorder) = recorder {
+        recorder.flush().expect("Failed to save game");
+    }
+    if let Some(last_message) = last_message {
+        let last_tick = last_message.tick + 1;
+        println!("Final score: {}", last_tick * 5);
+    }
+}
@@ -0,0 +1,93 @@
+/// Local game server, to play the real client (see 'application.py') against
+/// our maps, offline.
+///
+/// Example usage:
+///   cargo run --release --bin devnull_game_server -- --map challenge1
+///   # In another terminal:
+///   SAVE_JSONL_PATH=games/1.jsonl ./run.sh
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use rand::seq::SliceRandom;
+
+use devnull_bot::game_server::play_game;
+use devnull_bot::map_loader::{self, list_map_names, load_map};
+use devnull_bot::simulation::{GameOptions, State};
+
+#[derive(Debug, Parser)]
+#[clap(name = "devnull_game_server")]
+struct Cli {
+    #[arg(long, default_value_t = 8765, help = "Port to listen on, for 'ws://127.0.0.1:<port>'.")]
+    port: u16,
+    #[arg(long = "map", help = "Map to play. If unset, picks a random one for each game.")]
+    map_name: Option<String>,
+    #[arg(long, help = "How many games to serve before exiting. If unset, serve forever.")]
+    num_games: Option<usize>,
+    #[arg(long, help = "How long to wait for the client's command each tick. If unset, wait forever.")]
+    tick_timeout_ms: Option<u64>,
+    #[arg(long, help = "Last tick of the game, after which the player wins. Defaults to the server's.")]
+    end_tick: Option<usize>,
+    #[arg(long, help = "Also save ticks to this .jsonl file, like 'SAVE_JSONL_PATH'.")]
+    save_jsonl: Option<PathBuf>,
+    #[arg(long, help = "Where maps are. Defaults to '$DEVNULL_MAPS_DIR', or 'maps'.")]
+    maps_dir: Option<PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    map_loader::set_maps_dir(cli.maps_dir.clone());
+    let mut game_options = GameOptions::default();
+    if let Some(end_tick) = cli.end_tick {
+        game_options.end_tick = end_tick;
+    }
+    let map_names = list_map_names().expect("Error listing maps");
+    let listener = TcpListener::bind(("127.0.0.1", cli.port)).expect("Failed to bind");
+    println!("Listening on ws://127.0.0.1:{}", cli.port);
+    let mut games_played = 0;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Failed to accept connection: {}", e);
+                continue;
+            },
+        };
+        let map_name = cli.map_name.clone().unwrap_or_else(|| {
+            map_names.choose(&mut rand::thread_rng()).cloned().expect("no maps")
+        });
+        let map = load_map(&map_name).expect("Error loading map");
+        println!("Serving map {} to {:?}", map.name, stream.peer_addr());
+        stream.set_read_timeout(cli.tick_timeout_ms.map(Duration::from_millis))
+            .expect("Failed to set timeout");
+        let mut socket = match tungstenite::accept(stream) {
+            Ok(socket) => socket,
+            Err(e) => {
+                println!("Websocket handshake failed: {}", e);
+                continue;
+            },
+        };
+        let mut recorder = cli.save_jsonl.as_ref().map(|path| {
+            BufWriter::new(File::create(path).expect("Failed to create save file"))
+        });
+        let state = State::new_custom(map.game, game_options.clone());
+        match play_game(&mut socket, state, recorder.as_mut().map(|r| r as &mut dyn Write)) {
+            Ok(summary) => println!(
+                "[{}] Game end on map {}! Tick: {}, Score: {}, alive: {}",
+                summary.team_name, map.name, summary.tick, summary.score,
+                summary.alive),
+            Err(e) => println!("Game on map {} failed: {:?}", map.name, e),
+        }
+        if let Some(mut recorder) = recorder {
+            recorder.flush().expect("Failed to save game");
+        }
+        games_played += 1;
+        if cli.num_games.is_some_and(|n| games_played >= n) {
+            break;
+        }
+    }
+}
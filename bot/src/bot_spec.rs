@@ -0,0 +1,307 @@
+/// Bot specs: the search algorithm of a bot and how it searches (evaluator,
+/// budget, etc.), written as a string to pick bots from the command line or
+/// Python, e.g. 'show-off', 'show-off,budget=150ms,exploration=0.7' or
+/// 'step(repeat(200, select(ucb1(0.7), simulate(greedy)))),evaluator=ticks_survived'.
+///
+/// Format: '<algorithm>[,<option>=<value>]*', where the algorithm is a preset
+/// (a 'BotName', e.g. 'show-off') or a 'grammar' expression, and options are:
+///   exploration=<c>         UCB-1 exploration parameter of all 'select's
+///   iterations=<n>          times of all 'repeat's, i.e. iterations per step
+///   rollout=<policy>        policy of all rollouts, e.g. 'greedy(show_off)'
+///   workers=<n>             searches merged on each move (root parallelization)
+///   evaluator=<heuristic>   score of sequences found, e.g. 'ticks_survived'
+///   budget=<n>ms|<n>evals   search budget per move
+///   max-rollout=<n>         rollouts end after this many moves
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::grammar::{Expr, GrammarError, Heuristic, Policy, Selector};
+use crate::mcts;
+use crate::search::{BotName, MCTS};
+use crate::simulation::State;
+
+#[derive(Debug, PartialEq)]
+pub enum SpecError {
+    /// Neither a preset nor a valid expression.
+    UnknownAlgorithm { algorithm: String, error: GrammarError },
+    UnknownOption(String),
+    InvalidValue { option: String, value: String },
+}
+
+pub type Result<T> = std::result::Result<T, SpecError>;
+
+/// Threads used by root parallel presets (keep their docs in sync). Fixed
+/// (rather than the number of cores) so that games play the same on any
+/// machine for a given seed.
+const PARALLEL_WORKERS: usize = 4;
+
+/// Search budget per move of presets.
+const DEFAULT_BUDGET: Duration = Duration::from_millis(75);
+
+/// How a bot searches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BotSpec {
+    /// As written by the user (or the preset name), to refer to the bot.
+    pub name: String,
+    pub algorithm: Expr,
+    /// Copies of the algorithm searching on separate threads, see
+    /// 'mcts::root_parallel_algorithm'.
+    pub workers: usize,
+    pub evaluator: Heuristic,
+    pub budget: mcts::TimeOrEvalsBudget,
+    pub max_rollout_length: Option<usize>,
+}
+
+impl BotSpec {
+    /// Spec of a preset, named as the command line does (e.g. 'show-off').
+    pub fn preset(name: BotName) -> Self {
+        let uct = |policy| Expr::Step(Box::new(Expr::Repeat {
+            times: 100,
+            expr: Box::new(Expr::Select {
+                selector: Selector::Ucb1(2_f32.sqrt()),
+                expr: Box::new(Expr::Simulate(policy)),
+            }),
+        }));
+        let not_dead = Policy::Greedy(Heuristic::NotDead);
+        let show_off = Policy::Greedy(Heuristic::ShowOff);
+        let (algorithm, workers) = match name {
+            BotName::Sampling => (Expr::Simulate(Policy::Random), 1),
+            BotName::IterativeSampling100 => (Expr::Step(Box::new(Expr::Repeat {
+                times: 100,
+                expr: Box::new(Expr::Simulate(Policy::Random)),
+            })), 1),
+            BotName::Uct100RolloutsSqrt2C => (uct(Policy::Random), 1),
+            BotName::Uct100RolloutsSqrt2CGreedyNotDead => (uct(not_dead), 1),
+            BotName::ShowOff => (uct(show_off), 1),
+            BotName::RootParallelUct100RolloutsSqrt2CGreedyNotDead =>
+                (uct(not_dead), PARALLEL_WORKERS),
+            BotName::RootParallelShowOff => (uct(show_off), PARALLEL_WORKERS),
+            BotName::Nmcs1GreedyNotDead => (Expr::Nested { level: 1, policy: not_dead }, 1),
+            BotName::Nmcs2GreedyNotDead => (Expr::Nested { level: 2, policy: not_dead }, 1),
+        };
+        let name = name.to_possible_value().expect("preset name").get_name().to_string();
+        Self { name, workers, ..Self::from_algorithm(algorithm) }
+    }
+
+    /// Spec searching with 'algorithm', with the presets' other options.
+    pub fn from_algorithm(algorithm: Expr) -> Self {
+        Self {
+            name: algorithm.to_string(),
+            algorithm,
+            workers: 1,
+            evaluator: Heuristic::TicksSurvived,
+            budget: mcts::TimeOrEvalsBudget::Time(mcts::TimeBudget { max_time: DEFAULT_BUDGET }),
+            max_rollout_length: None,
+        }
+    }
+
+    /// Parse a spec, see the format above.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts = split_top_level(text.trim()).into_iter();
+        let algorithm = parts.next().unwrap_or_default();
+        let mut spec = match BotName::from_str(algorithm, true) {
+            Ok(name) => Self::preset(name),
+            Err(_) => Self::from_algorithm(Expr::parse(algorithm).map_err(|error| {
+                SpecError::UnknownAlgorithm { algorithm: algorithm.to_string(), error }
+            })?),
+        };
+        spec.name = text.trim().to_string();
+        for part in parts {
+            let Some((option, value)) = part.split_once('=') else {
+                return Err(SpecError::UnknownOption(part.to_string()));
+            };
+            let (option, value) = (option.trim(), value.trim());
+            let invalid = || SpecError::InvalidValue {
+                option: option.to_string(), value: value.to_string(),
+            };
+            match option {
+                "exploration" => spec.algorithm = spec.algorithm.with_exploration(
+                    value.parse().ok().filter(|c: &f32| c.is_finite() && *c >= 0.0)
+                        .ok_or_else(invalid)?),
+                "iterations" => spec.algorithm = spec.algorithm.with_repetitions(
+                    value.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?),
+                "rollout" => spec.algorithm = spec.algorithm.with_policy(
+                    value.parse().map_err(|_| invalid())?),
+                "workers" => spec.workers =
+                    value.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?,
+                "evaluator" => spec.evaluator = value.parse().map_err(|_| invalid())?,
+                "budget" => spec.budget = parse_budget(value).ok_or_else(invalid)?,
+                "max-rollout" => spec.max_rollout_length =
+                    Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?),
+                _ => return Err(SpecError::UnknownOption(option.to_string())),
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Same options, searching with another algorithm.
+    pub fn with_algorithm(&self, algorithm: Expr) -> Self {
+        Self { name: algorithm.to_string(), algorithm, ..self.clone() }
+    }
+
+    /// All options written out, parses back to the same spec (but for the
+    /// name).
+    pub fn describe(&self) -> String {
+        let budget = match &self.budget {
+            mcts::TimeOrEvalsBudget::Time(budget) => format!("{}ms", budget.max_time.as_millis()),
+            mcts::TimeOrEvalsBudget::EvalCalls(budget) => format!("{}evals", budget.max_evals),
+        };
+        let mut text = format!("{},workers={},evaluator={},budget={}", self.algorithm,
+                               self.workers, self.evaluator, budget);
+        if let Some(length) = self.max_rollout_length {
+            text += &format!(",max-rollout={}", length);
+        }
+        text
+    }
+
+    /// Search from 'state', with randomness from 'seed'.
+    pub fn make_algorithm<'a>(&self, state: State, seed: u64) -> mcts::Algorithm<'a, MCTS> {
+        let mut params = mcts::SearchParams::<MCTS>::new(self.budget.clone(), self.evaluator, seed);
+        if let Some(length) = self.max_rollout_length {
+            params.set_max_rollout_length(length);
+        }
+        if self.workers > 1 {
+            mcts::root_parallel_algorithm(params, self.workers, state, |params, state| {
+                self.algorithm.make_algorithm(params, state)
+            })
+        } else {
+            self.algorithm.make_algorithm(params, state)
+        }
+    }
+}
+
+/// Split on commas outside of parentheses (expressions have their own).
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+/// '75ms' or '1000evals'.
+fn parse_budget(text: &str) -> Option<mcts::TimeOrEvalsBudget> {
+    if let Some(millis) = text.strip_suffix("ms") {
+        let max_time = Duration::from_millis(millis.trim().parse().ok()?);
+        Some(mcts::TimeOrEvalsBudget::Time(mcts::TimeBudget { max_time }))
+    } else {
+        let max_evals = text.strip_suffix("evals")?.trim().parse().ok()?;
+        Some(mcts::TimeOrEvalsBudget::EvalCalls(mcts::EvalCallsBudget { max_evals }))
+    }
+}
+
+impl FromStr for BotSpec {
+    type Err = SpecError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse(text)
+    }
+}
+
+impl fmt::Display for BotSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecError::UnknownAlgorithm { algorithm, error } => {
+                let presets: Vec<String> = BotName::value_variants().iter()
+                    .filter_map(|name| name.to_possible_value())
+                    .map(|value| value.get_name().to_string())
+                    .collect();
+                write!(f, "'{}' is neither a preset ({}) nor an expression ({})",
+                       algorithm, presets.join(", "), error)
+            },
+            SpecError::UnknownOption(option) => write!(
+                f, "unknown option '{}' (exploration, iterations, rollout, workers, evaluator, \
+                    budget, max-rollout)", option),
+            SpecError::InvalidValue { option, value } =>
+                write!(f, "invalid value '{}' for option '{}'", value, option),
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{make_grid, Pos};
+    use crate::simulation::{Game, Style, Threat};
+
+    #[test]
+    fn test_parse() {
+        let show_off = BotSpec::parse("show-off").unwrap();
+        assert_eq!(show_off, BotSpec::preset(BotName::ShowOff));
+        assert_eq!(show_off.describe(),
+                   "step(repeat(100, select(ucb1(1.4142135), simulate(greedy(show_off))))),\
+                    workers=1,evaluator=ticks_survived,budget=75ms");
+
+        let spec = BotSpec::parse(
+            "show-off, exploration=0.7, iterations=200, rollout=greedy(threats_far), workers=2, \
+             evaluator=not_dead, budget=500evals, max-rollout=50").unwrap();
+        assert_eq!(spec.name, "show-off, exploration=0.7, iterations=200, \
+                               rollout=greedy(threats_far), workers=2, evaluator=not_dead, \
+                               budget=500evals, max-rollout=50");
+        assert_eq!(spec.describe(),
+                   "step(repeat(200, select(ucb1(0.7), simulate(greedy(threats_far))))),\
+                    workers=2,evaluator=not_dead,budget=500evals,max-rollout=50");
+        // Written out options parse back to the same spec.
+        let described = BotSpec::parse(&spec.describe()).unwrap();
+        assert_eq!(BotSpec { name: spec.name.clone(), ..described }, spec);
+
+        let expr = "nested(2, random)";
+        assert_eq!(BotSpec::parse(expr).unwrap(),
+                   BotSpec::from_algorithm(Expr::parse(expr).unwrap()));
+
+        assert!(matches!(BotSpec::parse("best-bot"), Err(SpecError::UnknownAlgorithm { .. })));
+        assert_eq!(BotSpec::parse("show-off,speed=2"),
+                   Err(SpecError::UnknownOption("speed".to_string())));
+        assert_eq!(BotSpec::parse("show-off,budget=75"), Err(SpecError::InvalidValue {
+            option: "budget".to_string(), value: "75".to_string(),
+        }));
+        assert!(matches!(BotSpec::parse("sampling,workers=0"),
+                         Err(SpecError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_presets_build() {
+        let grid = make_grid(vec![
+            "#######",
+            "#     #",
+            "# # # #",
+            "#     #",
+            "#######",
+        ]);
+        let threats = vec![Threat::spawn(Pos { x: 5, y: 3 }, Style::Bull)];
+        let state = State::new(Game { tick: 1, pos: Pos { x: 1, y: 1 }, grid, threats,
+                                      alive: true });
+        for name in BotName::value_variants() {
+            let spec = BotSpec::parse(&format!("{},budget=300evals",
+                                               BotSpec::preset(*name).name)).unwrap();
+            let mut algorithm = spec.make_algorithm(state.clone(), 0);
+            let results = algorithm.search();
+            assert!(results.next_action.is_some(), "{}", spec);
+            // Searches stop early once they find a win.
+            assert!(results.is_win || results.stats.num_evals >= 300, "{}", spec);
+        }
+    }
+}
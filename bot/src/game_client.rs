@@ -0,0 +1,147 @@
+/// Client side of the game's websocket protocol, playing with our 'Bot'
+/// directly (see 'application.py' for the Python equivalent).
+
+use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+use tungstenite::{Message, WebSocket};
+
+use crate::game_message::{self, Action, ClientMessage, TeamGameState};
+use crate::search::{Bot, DesyncHandling};
+use crate::simulation::Game;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug)]
+pub enum ClientError {
+    WebSocketError(Box<tungstenite::Error>),
+    /// Server message that we could not parse.
+    ParseError(serde_json::Error),
+    MessageError(game_message::MessageError),
+    /// Failed to save a tick.
+    RecordingError(std::io::Error),
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::WebSocketError(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::ParseError(e)
+    }
+}
+
+/// Register with 'registration', then play with the bot created by 'make_bot'
+/// on the first tick, until the server closes the connection. If 'recorder' is
+/// set, ticks are saved to it like 'SAVE_JSONL_PATH'. Returns the last game
+/// state seen, if any.
+pub fn play_game<'a, S: Read + Write>(
+    socket: &mut WebSocket<S>, registration: ClientMessage,
+    make_bot: impl FnOnce(&Game) -> Bot<'a>,
+    mut recorder: Option<&mut dyn Write>) -> Result<Option<TeamGameState>> {
+    socket.send(Message::text(serde_json::to_string(&registration)?))?;
+    let mut make_bot = Some(make_bot);
+    let mut bot = None;
+    let mut last_message = None;
+    loop {
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            // Note: on 'Message::Close', keep reading to complete the close
+            // handshake.
+            Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => {
+                // Connection is closed, the game is probably over.
+                println!("Websocket was closed.");
+                return Ok(last_message);
+            },
+            Ok(_) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let message: TeamGameState = serde_json::from_str(&text)?;
+        let start_time = Instant::now();
+        println!("Playing tick {}", message.tick);
+        if !message.last_tick_errors.is_empty() {
+            println!("Errors during last tick : {:?}", message.last_tick_errors);
+        }
+        let game = message.to_game().map_err(ClientError::MessageError)?;
+        let bot = bot.get_or_insert_with(|| (make_bot.take().unwrap())(&game));
+        // Like 'application.py', keep playing if the bot fails on a tick.
+        let picked = panic::catch_unwind(AssertUnwindSafe(|| bot.pick_move(&game)));
+        let actions: Vec<Action> = match picked {
+            Ok(next_move) => Action::from_move(next_move).into_iter().collect(),
+            Err(error) => {
+                let error = error.downcast_ref::<String>().map(String::as_str)
+                    .or_else(|| error.downcast_ref::<&str>().copied())
+                    .unwrap_or("unknown error");
+                println!("[ERROR] Bot failed on tick {} ({}), sending no action",
+                         message.tick, error);
+                // Our state might not match the server's anymore.
+                bot.desync_handling = DesyncHandling::Resync;
+                Vec::new()
+            },
+        };
+        if let Some(recorder) = recorder.as_mut() {
+            let line = serde_json::json!({ "game_message": message, "actions": actions });
+            writeln!(recorder, "{}", line).map_err(ClientError::RecordingError)?;
+        }
+        let command = ClientMessage::Command { tick: message.tick, actions };
+        socket.send(Message::text(serde_json::to_string(&command)?))?;
+        println!("Turn took {:.2}ms", start_time.elapsed().as_secs_f64() * 1000.0);
+        last_message = Some(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use crate::game_server;
+    use crate::grid::{make_grid, Pos};
+    use crate::replay;
+    use crate::simulation::{GameOptions, State, Style, Threat};
+
+    #[test]
+    fn test_play_game_against_server() {
+        let grid = make_grid(vec![
+            "#######",
+            "#     #",
+            "# # # #",
+            "#     #",
+            "#######",
+        ]);
+        let threats = vec![Threat::spawn(Pos { x: 1, y: 1 }, Style::Goldfish)];
+        let game = Game { tick: 1, pos: Pos { x: 5, y: 3 }, grid, threats, alive: true };
+        let mut options = GameOptions::default();
+        options.end_tick = 5;
+        let state = State::new_custom(game, options.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server_state = state.clone();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            game_server::play_game(&mut socket, server_state, None).unwrap()
+        });
+
+        let (mut socket, _) = tungstenite::connect(format!("ws://127.0.0.1:{}", port)).unwrap();
+        let registration = ClientMessage::Register {
+            team_name: Some("devnull".to_string()), token: None };
+        let mut saved = Vec::new();
+        let last = play_game(&mut socket, registration,
+                             |_| Bot::new_best(state.clone(), 42),
+                             Some(&mut saved)).unwrap().unwrap();
+        let summary = server.join().unwrap();
+
+        assert_eq!(summary.team_name, "devnull");
+        assert_eq!(last.tick as usize + 1, summary.tick);
+        // Our saved game matches the simulation.
+        let ticks = replay::parse_replay(&String::from_utf8(saved).unwrap()).unwrap();
+        assert_eq!(ticks.len(), last.tick as usize);
+        assert!(replay::replay(&ticks, options).unwrap().divergence.is_none());
+    }
+}
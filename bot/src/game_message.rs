@@ -0,0 +1,261 @@
+/// Messages exchanged with the game server, mirroring 'game_message.py'.
+/// Allows working with saved games (and the server) without going through
+/// Python.
+
+use serde::{Deserialize, Serialize};
+
+use crate::grid::{grid_dims, Grid, Move, Pos};
+use crate::simulation::{Game, Personality, SimulationAction, State, Style, Threat};
+
+pub type Result<T> = std::result::Result<T, MessageError>;
+
+#[derive(Debug)]
+pub enum MessageError {
+    UnknownDirection(String),
+    UnknownPersonality(String),
+    /// Map is wider or taller than 'MAX_GRID_SIZE'.
+    MapTooLarge { width: u32, height: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn to_pos(&self) -> Pos {
+        Pos { x: self.x as i16, y: self.y as i16 }
+    }
+
+    pub fn from_pos(pos: &Pos) -> Self {
+        Self { x: pos.x as i32, y: pos.y as i32 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TileType {
+    Empty,
+    Wall,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Constants {}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameMap {
+    pub width: u32,
+    pub height: u32,
+    /// Note: tiles[x][y]
+    pub tiles: Vec<Vec<TileType>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThreatMessage {
+    pub position: Position,
+    pub direction: String,
+    pub personality: String,
+    pub style: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YourCharacter {
+    pub id: String,
+    pub team_id: String,
+    pub position: Position,
+    pub alive: bool,
+    pub spawn_point: Position,
+    pub distances: Vec<Vec<Option<i32>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamGameState {
+    #[serde(rename = "type", default)]
+    pub message_type: String,
+    pub tick: u32,
+    pub current_tick_number: u32,
+    pub last_tick_errors: Vec<String>,
+    #[serde(default)]
+    pub constants: Constants,
+    pub your_character: YourCharacter,
+    pub threats: Vec<ThreatMessage>,
+    pub map: GameMap,
+}
+
+/// Message sent by the client to the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ClientMessage {
+    /// First message, with a token for the real server, or a team name for
+    /// local games.
+    Register {
+        #[serde(rename = "teamName", default, skip_serializing_if = "Option::is_none")]
+        team_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// Actions for a given tick.
+    Command { tick: u32, actions: Vec<Action> },
+}
+
+/// Action sent back to the server.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveTo { position: Position },
+}
+
+impl Action {
+    /// Action for a move, if any (no action means staying in place).
+    pub fn from_move(m: Option<Move>) -> Option<Self> {
+        m.map(|m| match m {
+            Move::Up => Action::MoveUp,
+            Move::Down => Action::MoveDown,
+            Move::Left => Action::MoveLeft,
+            Move::Right => Action::MoveRight,
+        })
+    }
+
+    /// Simulation equivalent of (optionally) sending this action.
+    pub fn to_simulation_action(action: Option<&Action>) -> SimulationAction {
+        match action {
+            None => SimulationAction::Move { direction: None },
+            Some(Action::MoveUp) => SimulationAction::Move { direction: Some(Move::Up) },
+            Some(Action::MoveDown) => SimulationAction::Move { direction: Some(Move::Down) },
+            Some(Action::MoveLeft) => SimulationAction::Move { direction: Some(Move::Left) },
+            Some(Action::MoveRight) => SimulationAction::Move { direction: Some(Move::Right) },
+            Some(Action::MoveTo { position }) =>
+                SimulationAction::MoveTo { position: position.to_pos() },
+        }
+    }
+}
+
+fn direction_name(m: Move) -> &'static str {
+    match m {
+        Move::Up => "up",
+        Move::Down => "down",
+        Move::Left => "left",
+        Move::Right => "right",
+    }
+}
+
+fn from_direction_name(direction: &str) -> Result<Move> {
+    match direction {
+        "up" => Ok(Move::Up),
+        "down" => Ok(Move::Down),
+        "left" => Ok(Move::Left),
+        "right" => Ok(Move::Right),
+        _ => Err(MessageError::UnknownDirection(direction.to_string())),
+    }
+}
+
+impl ThreatMessage {
+    pub fn to_threat(&self) -> Result<Threat> {
+        // Styles we don't know are kept as 'Unknown', see 'inference'.
+        let style = Style::from_name(&self.style).unwrap_or(Style::Unknown);
+        let mut t = Threat::spawn(self.position.to_pos(), style);
+        t.dir = from_direction_name(&self.direction)?;
+        t.personality = Personality::from_name(&self.personality)
+            .ok_or_else(|| MessageError::UnknownPersonality(self.personality.clone()))?;
+        Ok(t)
+    }
+
+    pub fn from_threat(threat: &Threat) -> Self {
+        Self {
+            position: Position::from_pos(&threat.pos),
+            direction: direction_name(threat.dir).to_string(),
+            personality: threat.personality.name().to_string(),
+            style: threat.style.name().to_string(),
+        }
+    }
+}
+
+impl GameMap {
+    pub fn to_grid(&self) -> Result<Grid> {
+        let (width, height) = grid_dims(self.width as usize, self.height as usize)
+            .ok_or(MessageError::MapTooLarge { width: self.width, height: self.height })?;
+        let tiles = self.tiles.iter()
+            .map(|column| column.iter().map(|&t| t == TileType::Wall).collect())
+            .collect();
+        Ok(Grid::new(width, height, tiles))
+    }
+
+    pub fn from_grid(grid: &Grid) -> Self {
+        let (width, height) = grid.dims();
+        let tiles = grid.tiles.iter()
+            .map(|column| column.iter().map(|&wall| {
+                if wall { TileType::Wall } else { TileType::Empty }
+            }).collect())
+            .collect();
+        Self { width: width as u32, height: height as u32, tiles }
+    }
+}
+
+impl TeamGameState {
+    pub fn to_game(&self) -> Result<Game> {
+        Ok(Game {
+            tick: self.tick as usize,
+            pos: self.your_character.position.to_pos(),
+            grid: self.map.to_grid()?,
+            threats: self.threats.iter().map(|t| t.to_threat())
+                .collect::<Result<_>>()?,
+            alive: self.your_character.alive,
+        })
+    }
+
+    /// Message the server would send for 'game'. Fields that we don't model
+    /// (ids, distances) are left empty.
+    pub fn from_game(game: &Game) -> Self {
+        let position = Position::from_pos(&game.pos);
+        Self {
+            message_type: "TICK".to_string(),
+            tick: game.tick as u32,
+            current_tick_number: game.tick as u32,
+            last_tick_errors: Vec::new(),
+            constants: Constants {},
+            your_character: YourCharacter {
+                id: String::new(),
+                team_id: String::new(),
+                position,
+                alive: game.alive,
+                spawn_point: position,
+                distances: Vec::new(),
+            },
+            threats: game.threats.iter().map(ThreatMessage::from_threat).collect(),
+            map: GameMap::from_grid(&game.grid),
+        }
+    }
+}
+
+/// Simulation action for the client's actions, reporting invalid ones (which
+/// are ignored, instead of crashing the simulation).
+pub fn validate_actions(state: &State, actions: &[Action],
+                        errors: &mut Vec<String>) -> SimulationAction {
+    if actions.len() > 1 {
+        errors.push(format!("Only one action per tick is allowed, got {}, using the first",
+                            actions.len()));
+    }
+    let Some(action) = actions.first() else {
+        return SimulationAction::Move { direction: None };
+    };
+    let grid = &state.grid.grid;
+    match Action::to_simulation_action(Some(action)) {
+        SimulationAction::Move { direction: Some(m) }
+            if !grid.is_empty(&state.pos.moved(m)) => {
+            errors.push(format!("Cannot move {:?} from {:?}, into a wall", m, state.pos));
+            SimulationAction::Move { direction: None }
+        },
+        SimulationAction::MoveTo { position } if !grid.is_empty(&position) => {
+            errors.push(format!("Cannot move to {:?}, not an empty tile", position));
+            SimulationAction::Move { direction: None }
+        },
+        action => action,
+    }
+}
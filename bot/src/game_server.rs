@@ -0,0 +1,228 @@
+/// Local stand-in for the game server, speaking the same websocket protocol as
+/// the real one (see 'application.py'), so that the real client can play
+/// end-to-end offline.
+
+use std::io::{Read, Write};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::game_message::{validate_actions, Action, ClientMessage, TeamGameState};
+use crate::simulation::State;
+
+pub type Result<T> = std::result::Result<T, ServerError>;
+
+#[derive(Debug)]
+pub enum ServerError {
+    WebSocketError(Box<tungstenite::Error>),
+    /// Message that is not valid JSON for the protocol.
+    ParseError(serde_json::Error),
+    /// The client closed the connection before registering.
+    NotRegistered,
+}
+
+impl From<tungstenite::Error> for ServerError {
+    fn from(e: tungstenite::Error) -> Self {
+        ServerError::WebSocketError(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(e: serde_json::Error) -> Self {
+        ServerError::ParseError(e)
+    }
+}
+
+/// Outcome of a served game.
+#[derive(Debug)]
+pub struct GameSummary {
+    pub team_name: String,
+    /// Tick the game ended on.
+    pub tick: usize,
+    pub score: usize,
+    pub alive: bool,
+}
+
+/// Play a game of 'state' with a connected client, until the game is over or
+/// the client disconnects. If 'recorder' is set, ticks are saved to it in the
+/// same '.jsonl' format as 'SAVE_JSONL_PATH'.
+/// To limit how long we wait for the client each tick, set a read timeout on
+/// the underlying stream: timeouts count as the client not doing anything.
+pub fn play_game<S: Read + Write>(
+    socket: &mut WebSocket<S>, mut state: State,
+    mut recorder: Option<&mut dyn Write>) -> Result<GameSummary> {
+    let team_name = wait_for_registration(socket)?;
+    println!("[{}] Registered, starting on tick {}", team_name, state.tick);
+    let mut errors = Vec::new();
+    loop {
+        // Note: the server checks for game over at the start of a tick, then
+        // ends the game without sending that tick (see 'on_end' in
+        // 'application.py').
+        state.game_over |= state.check_game_over();
+        if state.game_over {
+            break;
+        }
+        let mut message = TeamGameState::from_game(&state.to_game());
+        message.last_tick_errors = std::mem::take(&mut errors);
+        socket.send(Message::text(serde_json::to_string(&message)?))?;
+        let Some(actions) = read_command(socket, state.tick, &mut errors)? else {
+            println!("[{}] Client disconnected on tick {}", team_name, state.tick);
+            break;
+        };
+        if let Some(recorder) = recorder.as_mut() {
+            let line = serde_json::json!({ "game_message": message, "actions": actions });
+            writeln!(recorder, "{}", line).map_err(tungstenite::Error::Io)?;
+        }
+        let action = validate_actions(&state, &actions, &mut errors);
+        for error in &errors {
+            println!("[{}] Tick {}: {}", team_name, state.tick, error);
+        }
+        state.simulate_tick(action);
+    }
+    // Like the real server, closing the connection signals the end of the game.
+    match socket.close(None) {
+        Ok(()) | Err(tungstenite::Error::ConnectionClosed)
+            | Err(tungstenite::Error::AlreadyClosed) => {},
+        Err(e) => return Err(e.into()),
+    }
+    // Flush the close handshake, the client may have left already.
+    while socket.read().is_ok() {}
+    Ok(GameSummary {
+        team_name,
+        tick: state.tick,
+        score: state.score(),
+        alive: !state.game_over || state.player_won(),
+    })
+}
+
+fn wait_for_registration<S: Read + Write>(socket: &mut WebSocket<S>) -> Result<String> {
+    loop {
+        match read_message(socket)? {
+            Some(ClientMessage::Register { team_name, token }) => {
+                return Ok(team_name.or(token.map(|_| "<token>".to_string()))
+                          .unwrap_or_default());
+            },
+            Some(ClientMessage::Command { .. }) => {
+                println!("Ignoring command received before registration.");
+            },
+            None => return Err(ServerError::NotRegistered),
+        }
+    }
+}
+
+/// Wait for the client's actions for 'tick'. Returns None if the client
+/// disconnected.
+fn read_command<S: Read + Write>(socket: &mut WebSocket<S>, tick: usize,
+                                 errors: &mut Vec<String>) -> Result<Option<Vec<Action>>> {
+    loop {
+        match read_message(socket) {
+            Ok(Some(ClientMessage::Command { tick: command_tick, actions })) => {
+                if command_tick as usize == tick {
+                    return Ok(Some(actions));
+                }
+                // Probably a late answer to a tick that timed out.
+                errors.push(format!("Ignored command for tick {} on tick {}",
+                                    command_tick, tick));
+            },
+            Ok(Some(ClientMessage::Register { .. })) => {
+                errors.push("Already registered".to_string());
+            },
+            Ok(None) => return Ok(None),
+            Err(ServerError::WebSocketError(e))
+                if matches!(*e, tungstenite::Error::Io(ref e) if is_timeout(e)) => {
+                errors.push(format!("No command received in time for tick {}", tick));
+                return Ok(Some(Vec::new()));
+            },
+            Err(ServerError::ParseError(e)) => {
+                errors.push(format!("Invalid message: {}", e));
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+}
+
+/// Next protocol message, None if the connection was closed.
+fn read_message<S: Read + Write>(socket: &mut WebSocket<S>) -> Result<Option<ClientMessage>> {
+    loop {
+        let message = match socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match message {
+            Message::Text(text) => return Ok(Some(serde_json::from_str(&text)?)),
+            Message::Binary(data) => return Ok(Some(serde_json::from_slice(&data)?)),
+            Message::Close(_) => return Ok(None),
+            // Pings are answered by tungstenite.
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use crate::grid::{make_grid, Pos};
+    use crate::replay;
+    use crate::simulation::{Game, GameOptions};
+
+    #[test]
+    fn test_play_game_with_client() {
+        let grid = make_grid(vec![
+            "#####",
+            "#   #",
+            "#####",
+        ]);
+        let game = Game { tick: 1, pos: Pos { x: 1, y: 1 }, grid, threats: vec![], alive: true };
+        let mut options = GameOptions::default();
+        options.end_tick = 4;
+        let state = State::new_custom(game, options);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let mut saved = Vec::new();
+            let summary = play_game(&mut socket, state, Some(&mut saved)).unwrap();
+            (summary, String::from_utf8(saved).unwrap())
+        });
+
+        let (mut client, _) = tungstenite::connect(format!("ws://127.0.0.1:{}", port)).unwrap();
+        let register = ClientMessage::Register { team_name: Some("test".to_string()), token: None };
+        client.send(Message::text(serde_json::to_string(&register).unwrap())).unwrap();
+        let mut received = Vec::new();
+        loop {
+            let message = match client.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(_) => continue,
+                Err(_) => break,
+            };
+            let game: TeamGameState = serde_json::from_str(&message).unwrap();
+            // Walk right, first into the wall.
+            let actions = if game.tick == 1 { vec![Action::MoveUp] } else { vec![Action::MoveRight] };
+            let command = ClientMessage::Command { tick: game.tick, actions };
+            let _ = client.send(Message::text(serde_json::to_string(&command).unwrap()));
+            received.push(game);
+        }
+        let (summary, saved) = server.join().unwrap();
+
+        assert_eq!(received.iter().map(|g| g.tick).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(received[1].last_tick_errors.len(), 1, "{:?}", received[1].last_tick_errors);
+        assert_eq!(received[1].your_character.position, received[0].your_character.position);
+        assert_eq!(received[3].your_character.position.x, 3);
+        assert_eq!(summary.team_name, "test");
+        assert_eq!(summary.tick, 5);
+        assert!(summary.alive);
+        // Saved games can be replayed.
+        let ticks = replay::parse_replay(&saved).unwrap();
+        assert_eq!(ticks.len(), 4);
+        let report = replay::replay(&ticks, GameOptions::default()).unwrap();
+        assert!(report.divergence.is_none());
+    }
+}
@@ -0,0 +1,537 @@
+/// Text expressions of the Monte Carlo Search grammar (arXiv:1208.4692) that
+/// 'mcts' components implement, e.g.
+/// 'step(repeat(100, select(ucb1(1.41), simulate(greedy(not_dead)))))', to
+/// build search algorithms without code and meta search over them.
+///
+/// Grammar:
+///   expr      := simulate(policy) | nested(level, policy) | repeat(n, expr)
+///              | step(expr) | lookahead(expr) | select(selector, expr)
+///   policy    := random | greedy | greedy(heuristic)
+///   selector  := ucb1(c)
+///   heuristic := not_dead | show_off | threats_far | ticks_survived
+/// 'greedy' alone is short for 'greedy(not_dead)'.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand_chacha::ChaCha8Rng;
+use rand::SeedableRng;
+
+use crate::mcts;
+use crate::search::{NotDeadEval, NotDeadShowOffEval, ThreatsAreFarEval, TicksSurvivedEval};
+use crate::simulation::State;
+
+#[derive(Debug, PartialEq)]
+pub enum GrammarError {
+    /// Something else than expected at this byte offset of the expression.
+    Unexpected { offset: usize, expected: String },
+    /// Valid syntax, but the components can't be nested this way (see
+    /// 'Expr::check').
+    UnsupportedNesting(String),
+}
+
+pub type Result<T> = std::result::Result<T, GrammarError>;
+
+/// Heuristic maximized by greedy rollouts, also usable as the evaluator of a
+/// search (see 'search' evaluators).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Heuristic {
+    NotDead,
+    ShowOff,
+    ThreatsFar,
+    TicksSurvived,
+}
+
+const HEURISTICS: [Heuristic; 4] = [
+    Heuristic::NotDead, Heuristic::ShowOff, Heuristic::ThreatsFar, Heuristic::TicksSurvived,
+];
+
+impl Heuristic {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Heuristic::NotDead => "not_dead",
+            Heuristic::ShowOff => "show_off",
+            Heuristic::ThreatsFar => "threats_far",
+            Heuristic::TicksSurvived => "ticks_survived",
+        }
+    }
+}
+
+impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for Heuristic {
+    fn evaluate(&self, state: &State) -> mcts::Score {
+        match self {
+            Heuristic::NotDead => mcts::Evaluator::<Spec>::evaluate(&NotDeadEval {}, state),
+            Heuristic::ShowOff => mcts::Evaluator::<Spec>::evaluate(&NotDeadShowOffEval {}, state),
+            Heuristic::ThreatsFar => mcts::Evaluator::<Spec>::evaluate(&ThreatsAreFarEval {}, state),
+            Heuristic::TicksSurvived =>
+                mcts::Evaluator::<Spec>::evaluate(&TicksSurvivedEval {}, state),
+        }
+    }
+}
+
+/// Rollout policy of 'simulate' and 'nested'.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    Random,
+    Greedy(Heuristic),
+}
+
+impl Policy {
+    fn build<'a, Spec: mcts::MCTS<State = State> + 'a>(
+        &self, seed: u64) -> Box<dyn mcts::SimulationPolicy<Spec> + 'a + Send + Sync> {
+        match self {
+            Policy::Random => Box::new(mcts::RandomPolicy { rng: ChaCha8Rng::seed_from_u64(seed) }),
+            Policy::Greedy(heuristic) =>
+                Box::new(mcts::GreedyPolicy::new(seed, Box::new(*heuristic))),
+        }
+    }
+}
+
+/// Tree policy of 'select'.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selector {
+    /// UCB-1 with this exploration parameter.
+    Ucb1(f32),
+}
+
+/// Search algorithm as a tree of 'mcts' components.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Simulate(Policy),
+    Nested { level: usize, policy: Policy },
+    Repeat { times: usize, expr: Box<Expr> },
+    Step(Box<Expr>),
+    LookAhead(Box<Expr>),
+    Select { selector: Selector, expr: Box<Expr> },
+}
+
+impl Expr {
+    /// Parse and check an expression, see the grammar above.
+    pub fn parse(text: &str) -> Result<Expr> {
+        let mut parser = Parser { text, offset: 0 };
+        let expr = parser.expr()?;
+        parser.end()?;
+        expr.check()?;
+        Ok(expr)
+    }
+
+    /// Reject nestings the components don't support: sub-searches return the
+    /// best sequence seen anywhere, which 'step' can only follow at the top,
+    /// and 'select' looks up its tree from the prefixes it is given, so it
+    /// can't be under another component changing them ('lookahead', 'select').
+    pub fn check(&self) -> Result<()> {
+        self.check_nesting(true, false)
+    }
+
+    fn check_nesting(&self, is_top: bool, under_branching: bool) -> Result<()> {
+        match self {
+            Expr::Simulate(_) | Expr::Nested { .. } => Ok(()),
+            Expr::Repeat { times: 0, .. } =>
+                Err(GrammarError::UnsupportedNesting(format!("'{}' repeats 0 times", self))),
+            Expr::Repeat { expr, .. } => expr.check_nesting(false, under_branching),
+            Expr::Step(_) if !is_top => Err(GrammarError::UnsupportedNesting(
+                format!("'{}' must be the top component", self))),
+            Expr::Step(expr) => expr.check_nesting(false, under_branching),
+            Expr::LookAhead(expr) => expr.check_nesting(false, true),
+            Expr::Select { .. } if under_branching => Err(GrammarError::UnsupportedNesting(
+                format!("'{}' can't be under 'lookahead' or 'select'", self))),
+            Expr::Select { expr, .. } => expr.check_nesting(false, true),
+        }
+    }
+
+    /// Build the components, policies use 'seed' for their randomness.
+    pub fn build<'a, Spec: mcts::MCTS<State = State> + 'a>(
+        &self, seed: u64) -> Box<dyn mcts::SearchComponent<Spec> + 'a + Send + Sync> {
+        match self {
+            Expr::Simulate(policy) => Box::new(mcts::Simulate::new(policy.build(seed))),
+            Expr::Nested { level, policy } =>
+                Box::new(mcts::Nested::new(*level, policy.build(seed))),
+            Expr::Repeat { times, expr } => Box::new(mcts::Repeat::new(*times, expr.build(seed))),
+            Expr::Step(expr) => Box::new(mcts::Step::new(expr.build(seed))),
+            Expr::LookAhead(expr) => Box::new(mcts::LookAhead::new(expr.build(seed))),
+            Expr::Select { selector: Selector::Ucb1(exploration), expr } => Box::new(
+                mcts::Select::new(Box::new(mcts::Ucb1Selector { exploration: *exploration }),
+                                  expr.build(seed))),
+        }
+    }
+
+    /// Copy with 'f' applied to each component, children first.
+    fn rewrite(&self, f: &impl Fn(Expr) -> Expr) -> Expr {
+        let expr = match self {
+            Expr::Repeat { times, expr } =>
+                Expr::Repeat { times: *times, expr: Box::new(expr.rewrite(f)) },
+            Expr::Step(expr) => Expr::Step(Box::new(expr.rewrite(f))),
+            Expr::LookAhead(expr) => Expr::LookAhead(Box::new(expr.rewrite(f))),
+            Expr::Select { selector, expr } =>
+                Expr::Select { selector: *selector, expr: Box::new(expr.rewrite(f)) },
+            leaf => leaf.clone(),
+        };
+        f(expr)
+    }
+
+    /// Copy where all 'select's use UCB-1 with this exploration parameter.
+    pub fn with_exploration(&self, exploration: f32) -> Expr {
+        self.rewrite(&|expr| match expr {
+            Expr::Select { expr, .. } => Expr::Select { selector: Selector::Ucb1(exploration), expr },
+            expr => expr,
+        })
+    }
+
+    /// Copy where all 'repeat's repeat this many times.
+    pub fn with_repetitions(&self, times: usize) -> Expr {
+        self.rewrite(&|expr| match expr {
+            Expr::Repeat { expr, .. } => Expr::Repeat { times, expr },
+            expr => expr,
+        })
+    }
+
+    /// Copy where all rollouts follow this policy.
+    pub fn with_policy(&self, policy: Policy) -> Expr {
+        self.rewrite(&|expr| match expr {
+            Expr::Simulate(_) => Expr::Simulate(policy),
+            Expr::Nested { level, .. } => Expr::Nested { level, policy },
+            expr => expr,
+        })
+    }
+
+    /// Algorithm searching from 'state' with these components.
+    pub fn make_algorithm<'a, Spec: mcts::MCTS<State = State> + 'a>(
+        &self, params: mcts::SearchParams<Spec>, state: State) -> mcts::Algorithm<'a, Spec> {
+        mcts::Algorithm::new(self.build(params.seed), params, state)
+    }
+
+    /// Expressions for meta search: an optional 'step' over an optionally
+    /// repeated 'select', 'lookahead' or rollout, with a few values of each
+    /// parameter.
+    pub fn search_space() -> Vec<Expr> {
+        let policies: Vec<Policy> = std::iter::once(Policy::Random)
+            .chain(HEURISTICS.iter().map(|&h| Policy::Greedy(h)))
+            .collect();
+        let leaves: Vec<Expr> = policies.iter().flat_map(|&policy| [
+            Expr::Simulate(policy),
+            Expr::Nested { level: 1, policy },
+        ]).collect();
+        let trees: Vec<Expr> = leaves.iter().flat_map(|leaf| {
+            [0.5, 1.41, 2.0].into_iter()
+                .map(|c| Expr::Select { selector: Selector::Ucb1(c), expr: Box::new(leaf.clone()) })
+                .chain([Expr::LookAhead(Box::new(leaf.clone())), leaf.clone()])
+        }).collect();
+        let searches: Vec<Expr> = trees.iter().flat_map(|tree| {
+            [10, 100].into_iter()
+                .map(|times| Expr::Repeat { times, expr: Box::new(tree.clone()) })
+                .chain(std::iter::once(tree.clone()))
+        }).collect();
+        searches.iter()
+            .flat_map(|search| [Expr::Step(Box::new(search.clone())), search.clone()])
+            .collect()
+    }
+}
+
+impl fmt::Display for Heuristic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::Random => write!(f, "random"),
+            Policy::Greedy(heuristic) => write!(f, "greedy({})", heuristic),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::Ucb1(c) => write!(f, "ucb1({})", c),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Simulate(policy) => write!(f, "simulate({})", policy),
+            Expr::Nested { level, policy } => write!(f, "nested({}, {})", level, policy),
+            Expr::Repeat { times, expr } => write!(f, "repeat({}, {})", times, expr),
+            Expr::Step(expr) => write!(f, "step({})", expr),
+            Expr::LookAhead(expr) => write!(f, "lookahead({})", expr),
+            Expr::Select { selector, expr } => write!(f, "select({}, {})", selector, expr),
+        }
+    }
+}
+
+impl std::error::Error for GrammarError {}
+
+impl FromStr for Heuristic {
+    type Err = GrammarError;
+
+    fn from_str(text: &str) -> Result<Heuristic> {
+        let mut parser = Parser { text, offset: 0 };
+        let heuristic = parser.heuristic()?;
+        parser.end()?;
+        Ok(heuristic)
+    }
+}
+
+impl FromStr for Policy {
+    type Err = GrammarError;
+
+    fn from_str(text: &str) -> Result<Policy> {
+        let mut parser = Parser { text, offset: 0 };
+        let policy = parser.policy()?;
+        parser.end()?;
+        Ok(policy)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = GrammarError;
+
+    fn from_str(text: &str) -> Result<Expr> {
+        Expr::parse(text)
+    }
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrammarError::Unexpected { offset, expected } =>
+                write!(f, "expected {} at offset {}", expected, offset),
+            GrammarError::UnsupportedNesting(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Recursive descent parser, whitespace is ignored between tokens.
+struct Parser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T> {
+        Err(GrammarError::Unexpected { offset: self.offset, expected: expected.to_string() })
+    }
+
+    /// Consume 'token' if it is next.
+    fn accept(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.offset += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        if self.accept(token) { Ok(()) } else { self.error(&format!("'{}'", token)) }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        if self.rest().is_empty() { Ok(()) } else { self.error("end of expression") }
+    }
+
+    /// Next name (letters, digits and underscores).
+    fn name(&mut self) -> String {
+        self.skip_whitespace();
+        let len = self.rest().find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        let name = self.rest()[..len].to_string();
+        self.offset += len;
+        name
+    }
+
+    fn number<T: FromStr>(&mut self, expected: &str) -> Result<T> {
+        self.skip_whitespace();
+        let start = self.offset;
+        let len = self.rest().find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(self.rest().len());
+        match self.rest()[..len].parse() {
+            Ok(value) => {
+                self.offset += len;
+                Ok(value)
+            },
+            Err(_) => Err(GrammarError::Unexpected { offset: start, expected: expected.to_string() }),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let start = self.offset;
+        let name = self.name();
+        self.expect("(")?;
+        let expr = match name.as_str() {
+            "simulate" => Expr::Simulate(self.policy()?),
+            "nested" => {
+                let level = self.number("nesting level")?;
+                self.expect(",")?;
+                Expr::Nested { level, policy: self.policy()? }
+            },
+            "repeat" => {
+                let times = self.number("repetitions")?;
+                self.expect(",")?;
+                Expr::Repeat { times, expr: Box::new(self.expr()?) }
+            },
+            "step" => Expr::Step(Box::new(self.expr()?)),
+            "lookahead" => Expr::LookAhead(Box::new(self.expr()?)),
+            "select" => {
+                let selector = self.selector()?;
+                self.expect(",")?;
+                Expr::Select { selector, expr: Box::new(self.expr()?) }
+            },
+            _ => {
+                self.offset = start;
+                return self.error("component (simulate, nested, repeat, step, lookahead, select)");
+            },
+        };
+        self.expect(")")?;
+        Ok(expr)
+    }
+
+    fn policy(&mut self) -> Result<Policy> {
+        let start = self.offset;
+        match self.name().as_str() {
+            "random" => Ok(Policy::Random),
+            "greedy" if self.accept("(") => {
+                let heuristic = self.heuristic()?;
+                self.expect(")")?;
+                Ok(Policy::Greedy(heuristic))
+            },
+            "greedy" => Ok(Policy::Greedy(Heuristic::NotDead)),
+            _ => {
+                self.offset = start;
+                self.error("policy (random, greedy)")
+            },
+        }
+    }
+
+    fn heuristic(&mut self) -> Result<Heuristic> {
+        let start = self.offset;
+        let name = self.name();
+        match HEURISTICS.into_iter().find(|h| h.name() == name) {
+            Some(heuristic) => Ok(heuristic),
+            None => {
+                self.offset = start;
+                self.error("heuristic (not_dead, show_off, threats_far, ticks_survived)")
+            },
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector> {
+        let start = self.offset;
+        if self.name() != "ucb1" {
+            self.offset = start;
+            return self.error("selector (ucb1)");
+        }
+        self.expect("(")?;
+        let exploration = self.number("exploration parameter")?;
+        self.expect(")")?;
+        Ok(Selector::Ucb1(exploration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::make_grid;
+    use crate::grid::Pos;
+    use crate::search::{Action, ActionSpace};
+    use crate::simulation::{Game, Style, Threat};
+
+    struct EvalsMCTS;
+    impl mcts::MCTS for EvalsMCTS {
+        type Action = Action;
+        type State = State;
+        type ActionSpace = ActionSpace;
+        type Evaluator = TicksSurvivedEval;
+        type Budget = mcts::EvalCallsBudget;
+    }
+
+    #[test]
+    fn test_parse() {
+        let expr = Expr::parse(
+            " step( repeat(100, select(ucb1(1.41), simulate(greedy)))) ").unwrap();
+        assert_eq!(expr, Expr::Step(Box::new(Expr::Repeat {
+            times: 100,
+            expr: Box::new(Expr::Select {
+                selector: Selector::Ucb1(1.41),
+                expr: Box::new(Expr::Simulate(Policy::Greedy(Heuristic::NotDead))),
+            }),
+        })));
+        assert_eq!(expr.to_string(),
+                   "step(repeat(100, select(ucb1(1.41), simulate(greedy(not_dead)))))");
+        assert_eq!(Expr::parse("nested(2, greedy(show_off))").unwrap(),
+                   Expr::Nested { level: 2, policy: Policy::Greedy(Heuristic::ShowOff) });
+
+        assert_eq!(Expr::parse("step(simulate(greedy(not_alive)))"),
+                   Err(GrammarError::Unexpected {
+                       offset: 21,
+                       expected: "heuristic (not_dead, show_off, threats_far, ticks_survived)"
+                           .to_string(),
+                   }));
+        assert_eq!(Expr::parse("repeat(x, simulate(random))"),
+                   Err(GrammarError::Unexpected { offset: 7, expected: "repetitions".to_string() }));
+        assert_eq!(Expr::parse("simulate(random))"),
+                   Err(GrammarError::Unexpected {
+                       offset: 16, expected: "end of expression".to_string(),
+                   }));
+        assert!(matches!(Expr::parse("repeat(10, step(simulate(random)))"),
+                         Err(GrammarError::UnsupportedNesting(_))));
+        assert!(matches!(Expr::parse("lookahead(select(ucb1(1), simulate(random)))"),
+                         Err(GrammarError::UnsupportedNesting(_))));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let expr = Expr::parse("step(repeat(10, select(ucb1(2), repeat(3, nested(1, random)))))")
+            .unwrap();
+        assert_eq!(expr.with_exploration(0.5).with_repetitions(100)
+                       .with_policy("greedy(threats_far)".parse().unwrap()).to_string(),
+                   "step(repeat(100, select(ucb1(0.5), repeat(100, \
+                    nested(1, greedy(threats_far))))))");
+        assert!("greedy(".parse::<Policy>().is_err());
+        assert_eq!("ticks_survived".parse(), Ok(Heuristic::TicksSurvived));
+    }
+
+    #[test]
+    fn test_search_space() {
+        let space = Expr::search_space();
+        assert_eq!(space.len(), 300);
+        for expr in &space {
+            assert_eq!(Expr::parse(&expr.to_string()).as_ref(), Ok(expr));
+        }
+
+        let grid = make_grid(vec![
+            "#######",
+            "#     #",
+            "# # # #",
+            "#     #",
+            "#######",
+        ]);
+        let threats = vec![Threat::spawn(Pos { x: 5, y: 3 }, Style::Bull)];
+        let state = State::new(Game { tick: 1, pos: Pos { x: 1, y: 1 }, grid, threats,
+                                      alive: true });
+        // Every kind of component, in any supported nesting, can play.
+        for expr in &space {
+            let params = mcts::SearchParams::<EvalsMCTS>::new(
+                mcts::EvalCallsBudget { max_evals: 200 }, TicksSurvivedEval {}, 0);
+            let mut algorithm = expr.make_algorithm(params, state.clone());
+            for _ in 0..3 {
+                assert!(algorithm.search().next_action.is_some(), "{}", expr);
+            }
+        }
+    }
+}
@@ -45,13 +45,31 @@ impl Pos {
     }
 
     pub fn manhattan_dist(&self, other: &Pos) -> usize {
-        ((self.x - other.x).abs() + (self.y - other.y).abs()) as usize
+        let (dx, dy) = self.abs_diff(other);
+        dx + dy
     }
 
     pub fn dist_squared(&self, other: &Pos) -> usize {
-        let dx = self.x - other.x;
-        let dy = self.y - other.y;
-        (dx * dx + dy * dy) as usize
+        let (dx, dy) = self.abs_diff(other);
+        dx * dx + dy * dy
+    }
+
+    /// Differences in x and y, without overflowing on large grids.
+    fn abs_diff(&self, other: &Pos) -> (usize, usize) {
+        ((self.x as i32 - other.x as i32).unsigned_abs() as usize,
+         (self.y as i32 - other.y as i32).unsigned_abs() as usize)
+    }
+}
+
+/// Largest width or height of a grid, so that all positions fit in a 'Pos'.
+pub const MAX_GRID_SIZE: usize = i16::MAX as usize;
+
+/// Dimensions to create a 'Grid' with, if they are at most 'MAX_GRID_SIZE'.
+pub fn grid_dims(width: usize, height: usize) -> Option<(u16, u16)> {
+    if width > MAX_GRID_SIZE || height > MAX_GRID_SIZE {
+        None
+    } else {
+        Some((width as u16, height as u16))
     }
 }
 
@@ -59,8 +77,8 @@ pub type EmptyTile = usize;
 
 #[derive(Clone, PartialEq)]
 pub struct Grid {
-    pub width: u8,
-    pub height: u8,
+    pub width: u16,
+    pub height: u16,
     /// Dims: [x][y], true for walls
     pub tiles: Vec<Vec<bool>>,
 
@@ -77,7 +95,9 @@ pub struct Grid {
 }
 
 impl Grid {
-    pub fn new(width: u8, height: u8, tiles: Vec<Vec<bool>>) -> Self {
+    pub fn new(width: u16, height: u16, tiles: Vec<Vec<bool>>) -> Self {
+        assert!(grid_dims(width as usize, height as usize).is_some(),
+                "{}x{} grid is too large", width, height);
         let mut empty_tiles = Vec::new();
         let mut empty_tiles_lookup = vec![
             vec![usize::MAX; height as usize]; width as usize];
@@ -107,7 +127,7 @@ impl Grid {
         grid
     }
 
-    pub fn dims(&self) -> (u8, u8) {
+    pub fn dims(&self) -> (u16, u16) {
         (self.width, self.height)
     }
 
@@ -125,7 +145,7 @@ impl Grid {
     }
 
     pub fn on_grid(&self, pos: &Pos) -> bool {
-        pos.x >= 0 && pos.x < self.width.into() && pos.y >= 0 && pos.y < self.height.into()
+        pos.x >= 0 && (pos.x as u16) < self.width && pos.y >= 0 && (pos.y as u16) < self.height
     }
 
     pub fn empty_tile_idx(&self, pos: &Pos) -> usize {
@@ -212,7 +232,7 @@ pub fn make_grid(rows: Vec<&str>) -> Grid {
             tiles[x][y] = c == b'#';
         }
     }
-    Grid::new(width as u8, height as u8, tiles)
+    Grid::new(width as u16, height as u16, tiles)
 }
 
 #[allow(dead_code)] 
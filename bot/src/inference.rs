@@ -0,0 +1,446 @@
+/// Recovery of the threat state that the server never sends us (RNG seed,
+/// Owl/Hawk memory, Deer spawn) from a sequence of observed games, so that the
+/// simulator can take over a game from an arbitrary tick (e.g. when joining
+/// mid-game or after a desync).
+///
+/// We try every plausible hidden state for each threat, replay it through the
+/// observations, and keep the ones that explain every observed move.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+
+use crate::grid::Grid;
+use crate::pathfinding::PathfindingGrid;
+use crate::simulation::{Game, GameOptions, State, Style, Threat, ThreatStorage};
+
+pub type Result<T> = std::result::Result<T, InferenceError>;
+
+#[derive(Debug)]
+pub enum InferenceError {
+    NoObservations,
+    /// Observations must be one tick apart. Tick where this was not the case.
+    NonConsecutiveTicks { tick: usize },
+    /// The number or styles of threats changed on this tick.
+    ThreatsChanged { tick: usize },
+    /// No hidden state explains the observed moves of this threat.
+    NoConsistentState { threat: usize, style: Style },
+    /// Ran out of time before trying every hidden state.
+    OutOfTime,
+}
+
+/// How to infer hidden state.
+#[derive(Clone)]
+pub struct InferenceOptions {
+    pub game: GameOptions,
+    /// Pathfinding of the observed grid, if already computed. Computing it
+    /// from scratch is expensive on large maps.
+    pub grid: Option<Arc<PathfindingGrid>>,
+    /// Give up past this much time. Trying every hidden state takes a while
+    /// for long observations on large maps.
+    pub max_time: Option<Duration>,
+}
+impl InferenceOptions {
+    pub fn new(game: GameOptions) -> Self {
+        Self { game, grid: None, max_time: None }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.max_time.map(|max_time| Instant::now() + max_time)
+    }
+
+    /// State at 'game', ignoring any starting tick override: the observed
+    /// tick is the truth, not the one the game started at.
+    fn make_state(&self, game: Game) -> State {
+        let options = GameOptions { starting_tick: None, ..self.game.clone() };
+        match &self.grid {
+            Some(grid) => State::with_grid(game, grid.clone(), options),
+            None => State::new_custom(game, options),
+        }
+    }
+}
+
+/// Outcome of inferring hidden state from observations.
+pub struct Inference {
+    /// State at the last observation, ready to be simulated.
+    pub state: State,
+    /// Per threat, distinct hidden states that explained the observations, at
+    /// the last observation. 'state' uses the first one. If there are more,
+    /// we could still mispredict later.
+    pub candidates: Vec<Vec<Threat>>,
+}
+
+impl Inference {
+    pub fn is_ambiguous(&self) -> bool {
+        self.candidates.iter().any(|c| c.len() > 1)
+    }
+}
+
+/// Infer hidden threat state from consecutive observations (one per tick).
+/// The more observations, the less ambiguous the results.
+pub fn infer_state(observations: &[Game], options: &InferenceOptions) -> Result<Inference> {
+    let deadline = options.deadline();
+    let (first, last) = check_observations(observations)?;
+    let starting_tick = options.game.starting_tick.unwrap_or(1);
+    let mut state = options.make_state(last.clone());
+    if last.tick > starting_tick {
+        // After simulating a tick, threats see the player's previous position
+        // as its current one.
+        state.prev_pos = last.pos;
+    }
+    // Each tick consumes at most one random number per threat, on top of the
+    // one used to pick the initial direction.
+    let max_seed = first.tick + 1;
+    let mut candidates = Vec::new();
+    for (i, observed) in first.threats.iter().enumerate() {
+        let consistent = if observed.style == Style::Unknown {
+            // Nothing to infer, we can't simulate it anyway.
+            vec![state.threats[i].clone()]
+        } else {
+            consistent_threats(observed, i, observations, &state, max_seed, deadline)?.0
+        };
+        if consistent.is_empty() {
+            return Err(InferenceError::NoConsistentState { threat: i, style: observed.style });
+        }
+        candidates.push(consistent);
+    }
+    state.threats = candidates.iter().map(|c| c[0].clone()).collect();
+    Ok(Inference { state, candidates })
+}
+
+/// Infer the hidden state of a single threat (index 'threat', of known style),
+/// e.g. to trust it again after mispredictions. Much cheaper than
+/// 'infer_state' when only a few threats need it. Returns distinct consistent
+/// threats at the last observation.
+pub fn infer_threat(observations: &[Game], threat: usize,
+                    options: &InferenceOptions) -> Result<Vec<Threat>> {
+    let deadline = options.deadline();
+    let (first, last) = check_observations(observations)?;
+    let state = options.make_state(last.clone());
+    let observed = &first.threats[threat];
+    let (consistent, _) = consistent_threats(
+        observed, threat, observations, &state, first.tick + 1, deadline)?;
+    if consistent.is_empty() {
+        return Err(InferenceError::NoConsistentState { threat, style: observed.style });
+    }
+    Ok(consistent)
+}
+
+/// First and last observations, if observations are one tick apart, with the
+/// same threats.
+fn check_observations(observations: &[Game]) -> Result<(&Game, &Game)> {
+    let first = observations.first().ok_or(InferenceError::NoObservations)?;
+    for (prev, next) in observations.iter().tuple_windows() {
+        if next.tick != prev.tick + 1 {
+            return Err(InferenceError::NonConsecutiveTicks { tick: next.tick });
+        }
+        if next.threats.len() != prev.threats.len() ||
+            next.threats.iter().zip(prev.threats.iter()).any(|(a, b)| a.style != b.style) {
+            return Err(InferenceError::ThreatsChanged { tick: next.tick });
+        }
+    }
+    Ok((first, observations.last().unwrap()))
+}
+
+/// Known style that explains the observed moves of a threat.
+pub struct StyleMatch {
+    pub style: Style,
+    /// Ratio of possible hidden states that explain the observations. Styles
+    /// that rely on randomness (e.g. goldfish) can explain many trajectories,
+    /// but only with a small fraction of their hidden states.
+    pub likelihood: f64,
+    /// Distinct consistent threats at the last observation.
+    pub candidates: Vec<Threat>,
+}
+
+/// Outcome of 'identify_style'.
+pub struct Identification {
+    /// Styles that explain the observed moves, most likely first.
+    pub matches: Vec<StyleMatch>,
+    /// Styles we ran out of time to check, they might explain the moves too.
+    pub unchecked: Vec<Style>,
+}
+
+/// Find which known styles could explain the observed moves of a threat of
+/// unknown style (index 'threat'). Only meaningful if the threat moved enough
+/// in the observations, see 'count_moves'.
+pub fn identify_style(observations: &[Game], threat: usize,
+                      options: &InferenceOptions) -> Identification {
+    let deadline = options.deadline();
+    let mut identification = Identification { matches: Vec::new(), unchecked: Vec::new() };
+    let (Some(first), Some(last)) = (observations.first(), observations.last()) else {
+        return identification;
+    };
+    let state = options.make_state(last.clone());
+    let max_seed = first.tick + 1;
+    for style in Style::known() {
+        let mut observed = first.threats[threat].clone();
+        observed.style = style;
+        match consistent_threats(&observed, threat, observations, &state, max_seed, deadline) {
+            Ok((candidates, _)) if candidates.is_empty() => {},
+            Ok((candidates, likelihood)) =>
+                identification.matches.push(StyleMatch { style, likelihood, candidates }),
+            Err(_) => identification.unchecked.push(style),
+        }
+    }
+    identification.matches.sort_by(|a, b| b.likelihood.total_cmp(&a.likelihood));
+    identification
+}
+
+/// Pick the style to adopt from 'identify_style' matches, if one is clearly
+/// more likely than the others.
+pub fn most_likely_style(matches: &[StyleMatch]) -> Option<&StyleMatch> {
+    // How much more likely the best match must be than the runner-up.
+    const MIN_LIKELIHOOD_RATIO: f64 = 10.0;
+    match matches {
+        [] => None,
+        [best] => Some(best),
+        [best, second, ..] => (best.likelihood >= second.likelihood * MIN_LIKELIHOOD_RATIO)
+            .then_some(best),
+    }
+}
+
+/// How many times a threat moved across observations.
+pub fn count_moves(observations: &[Game], threat: usize) -> usize {
+    observations.iter().tuple_windows()
+        .filter(|(prev, next)| prev.threats[threat].pos != next.threats[threat].pos)
+        .count()
+}
+
+/// Distinct threats (at the last observation) whose hidden state explains all
+/// observed moves of threat 'idx', assuming it has the style of 'observed'.
+/// Also returns the ratio of tried hidden states that were consistent.
+fn consistent_threats(observed: &Threat, idx: usize, observations: &[Game],
+                      state: &State, max_seed: usize,
+                      deadline: Option<Instant>) -> Result<(Vec<Threat>, f64)> {
+    let mut consistent: Vec<Threat> = Vec::new();
+    let candidates = candidate_threats(observed, &state.grid.grid, max_seed);
+    let num_candidates = candidates.len();
+    let mut num_consistent = 0;
+    for candidate in candidates {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(InferenceError::OutOfTime);
+        }
+        if let Some(threat) = replay_threat(candidate, idx, observations, state) {
+            num_consistent += 1;
+            // Different starting points can converge to the same state (e.g.
+            // owls forgetting their old target).
+            if !consistent.contains(&threat) {
+                consistent.push(threat);
+            }
+        }
+    }
+    Ok((consistent, num_consistent as f64 / num_candidates as f64))
+}
+
+/// Possible threats (with hidden state) given an observed threat. More likely
+/// candidates come first.
+fn candidate_threats(observed: &Threat, grid: &Grid, max_seed: usize) -> Vec<Threat> {
+    let storages: Vec<Option<ThreatStorage>> = match observed.style {
+        Style::Owl => std::iter::once(None)
+            .chain(grid.empty_tiles.iter().map(|&target_last_seen| {
+                Some(ThreatStorage::Owl { target_last_seen })
+            })).collect(),
+        // Following sheriff.js, only one of 'target_last_seen',
+        // 'idle_position' or 'idle_rounds' is ever set at a time.
+        Style::Hawk => (-5..=5).map(|idle_rounds| ThreatStorage::Hawk {
+                target_last_seen: None, idle_position: None, idle_rounds,
+            })
+            .chain(grid.best_intersections.iter().map(|&p| ThreatStorage::Hawk {
+                target_last_seen: None, idle_position: Some(p), idle_rounds: 0,
+            }))
+            .chain(grid.empty_tiles.iter().map(|&p| ThreatStorage::Hawk {
+                target_last_seen: Some(p), idle_position: None, idle_rounds: 0,
+            }))
+            .map(Some).collect(),
+        _ => vec![None],
+    };
+    let seeds: Vec<usize> = match observed.style {
+        Style::Goldfish | Style::Bull | Style::Hawk => (0..=max_seed).collect(),
+        // Does not use random numbers (or we can't know, for unknown styles).
+        Style::Shark | Style::Owl | Style::Deer | Style::Unknown => vec![observed.seed],
+    };
+    let spawns = match observed.style {
+        Style::Deer => std::iter::once(observed.spawn)
+            .chain(grid.empty_tiles.iter().filter(|&&p| p != observed.spawn).cloned())
+            .collect(),
+        // Only deers care about their spawn.
+        _ => vec![observed.spawn],
+    };
+    let mut candidates = Vec::new();
+    for storage in &storages {
+        for &seed in &seeds {
+            for &spawn in &spawns {
+                let mut threat = observed.clone();
+                threat.storage = storage.clone();
+                threat.seed = seed;
+                threat.spawn = spawn;
+                candidates.push(threat);
+            }
+        }
+    }
+    candidates
+}
+
+/// Simulate a candidate threat through the observations, returning it at the
+/// last observation if it matched all observed moves.
+fn replay_threat(mut threat: Threat, idx: usize, observations: &[Game],
+                 state: &State) -> Option<Threat> {
+    for (prev, next) in observations.iter().tuple_windows() {
+        if !next.alive {
+            // Nothing moves once the game is over.
+            break;
+        }
+        threat.simulate(prev.tick, state.moves_on_tick(prev.tick), &next.pos,
+                        &prev.pos, &state.grid, &state.options().stochastic_moves);
+        let actual = &next.threats[idx];
+        if threat.pos != actual.pos || threat.dir != actual.dir {
+            return None;
+        }
+    }
+    Some(threat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{make_grid, Grid, Pos};
+    use crate::simulation::SimulationAction;
+
+    fn make_test_grid() -> Grid {
+        make_grid(vec![
+            "######################",
+            "#                    #",
+            "# ########  ######## #",
+            "# #                # #",
+            "# #### ###  # #### # #",
+            "# ####      # #### # #",
+            "# #### ###  # #### # #",
+            "#           # #      #",
+            "# #### ###  # # #### #",
+            "# #  #      # # #  # #",
+            "# #    ###  # #    # #",
+            "# #                # #",
+            "# ########  ######## #",
+            "#                    #",
+            "######################",
+        ])
+    }
+
+    /// Record a game where the player runs away from threats.
+    fn record_game(game: Game, max_ticks: usize) -> Vec<State> {
+        let mut state = State::new(game);
+        let mut history = vec![state.clone()];
+        while !state.game_over && history.len() < max_ticks {
+            let direction = state.generate_moves().max_by_key(|&m| {
+                let pos = m.map_or(state.pos, |m| state.pos.moved(m));
+                state.threats.iter().map(|t| state.grid.get_cost(&pos, &t.pos))
+                    .min().unwrap()
+            }).unwrap();
+            state.simulate_tick(SimulationAction::Move { direction });
+            history.push(state.clone());
+        }
+        history
+    }
+
+    /// Strip the hidden state of threats, like when reading them from the
+    /// server.
+    fn observe(state: &State) -> Game {
+        let mut game = state.to_game();
+        game.threats = game.threats.iter().map(|t| {
+            let mut observed = Threat::spawn(t.pos, t.style);
+            observed.dir = t.dir;
+            observed.personality = t.personality;
+            observed
+        }).collect();
+        game
+    }
+
+    #[test]
+    fn test_infer_state_predicts_future() {
+        let grid = make_test_grid();
+        let game = Game {
+            tick: 1,
+            pos: Pos { x: 11, y: 7 },
+            grid,
+            threats: vec![
+                Threat::spawn(Pos { x: 1, y: 1 }, Style::Goldfish),
+                Threat::spawn(Pos { x: 20, y: 1 }, Style::Bull),
+                Threat::spawn(Pos { x: 1, y: 13 }, Style::Owl),
+                Threat::spawn(Pos { x: 20, y: 13 }, Style::Hawk),
+            ],
+            alive: true,
+        };
+        let history = record_game(game, 200);
+        assert!(history.len() > 120, "test scenario should not die too early");
+
+        let observations: Vec<Game> = history[40..90].iter().map(observe).collect();
+        let options = InferenceOptions::new(GameOptions::default());
+        let inference = infer_state(&observations, &options).expect("inference");
+        let inferred = &inference.state;
+        assert!(inferred.find_mispredictions(&history[89].to_game()).is_empty());
+        let options = InferenceOptions {
+            game: GameOptions { starting_tick: Some(1), ..GameOptions::default() },
+            grid: Some(inferred.grid.clone()),
+            max_time: None,
+        };
+        let restarted = infer_state(&observations, &options).expect("inference");
+        assert_eq!(restarted.state.tick, history[89].tick);
+        // Same hidden states when inferring a single threat.
+        for (i, candidates) in inference.candidates.iter().enumerate() {
+            assert!(infer_threat(&observations, i, &options).expect("threat") == *candidates);
+        }
+        let options = InferenceOptions { max_time: Some(Duration::ZERO), ..options };
+        assert!(matches!(infer_state(&observations, &options), Err(InferenceError::OutOfTime)));
+        // Some hidden state can't be disambiguated from observations, but the
+        // real one should be among our candidates.
+        for (i, candidates) in inference.candidates.iter().enumerate() {
+            let explains_future = |threat: &Threat| {
+                let mut threat = threat.clone();
+                history[89..].iter().tuple_windows().all(|(prev, next)| {
+                    threat.simulate(prev.tick, inferred.moves_on_tick(prev.tick),
+                                    &next.pos, &prev.pos, &inferred.grid,
+                                    &inferred.options().stochastic_moves);
+                    threat.pos == next.threats[i].pos && threat.dir == next.threats[i].dir
+                })
+            };
+            assert!(candidates.iter().any(explains_future), "threat #{}", i);
+        }
+    }
+
+    #[test]
+    fn test_identify_style_of_unknown_threat() {
+        let game = Game {
+            tick: 1,
+            pos: Pos { x: 11, y: 7 },
+            grid: make_test_grid(),
+            threats: vec![
+                Threat::spawn(Pos { x: 1, y: 1 }, Style::Shark),
+                Threat::spawn(Pos { x: 20, y: 13 }, Style::Deer),
+            ],
+            alive: true,
+        };
+        let history = record_game(game, 200);
+        assert!(history.len() > 100, "test scenario should not die too early");
+        let observations: Vec<Game> = history[40..100].iter().map(|state| {
+            let mut game = observe(state);
+            game.threats.iter_mut().for_each(|t| t.style = Style::Unknown);
+            game
+        }).collect();
+        for (i, style) in [Style::Shark, Style::Deer].into_iter().enumerate() {
+            assert!(count_moves(&observations, i) > 5);
+            let options = InferenceOptions::new(GameOptions::default());
+            let identification = identify_style(&observations, i, &options);
+            assert!(identification.unchecked.is_empty());
+            assert_eq!(most_likely_style(&identification.matches).map(|m| m.style), Some(style));
+        }
+        // Out of time, styles are skipped rather than failing altogether.
+        let options = InferenceOptions {
+            max_time: Some(Duration::ZERO), ..InferenceOptions::new(GameOptions::default())
+        };
+        let identification = identify_style(&observations, 0, &options);
+        assert!(identification.matches.is_empty());
+        assert_eq!(identification.unchecked, Style::known().collect::<Vec<_>>());
+    }
+}
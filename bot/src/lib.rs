@@ -2,20 +2,33 @@
 /// A lot of duplication here, do this so that other parts of the Rust code can
 /// ignore that it can run inside Python.
 
+pub mod bot_spec;
+pub mod game_client;
+pub mod game_message;
+pub mod game_server;
+pub mod grammar;
 pub mod grid;
+pub mod inference;
+pub mod map_generator;
 pub mod map_loader;
 pub mod mcts;
 pub mod pathfinding;
+pub mod render;
+pub mod replay;
 pub mod search;
 pub mod simulation;
+pub mod viewer;
+
+use std::path::PathBuf;
 
 use pyo3::prelude::*;
 use pyo3::exceptions::PyValueError;
 
-use crate::grid::{debug_print, Grid, Move, Pos};
-use crate::map_loader::{load_all_maps, Map};
-use crate::search::Bot;
-use crate::simulation::{Game, State, Style, Threat};
+use crate::grid::{grid_dims, Grid, Move, Pos, MAX_GRID_SIZE};
+use crate::map_loader::find_or_create_map;
+use crate::bot_spec::BotSpec;
+use crate::search::{Bot, DesyncHandling};
+use crate::simulation::{Game, Personality, State, Style, Threat};
 
 #[pyclass]
 #[derive(Clone)]
@@ -98,22 +111,26 @@ pub struct GameThreat {
     #[pyo3(get, set)]
     pub direction: GameDirection,
     #[pyo3(get, set)]
+    pub personality: String,
+    #[pyo3(get, set)]
     pub style: String,
 }
 
 #[pymethods]
 impl GameThreat {
     #[new]
-    pub fn new(position: GamePosition, direction: GameDirection, style: String) -> GameThreat {
-        GameThreat { position, direction, style }
+    pub fn new(position: GamePosition, direction: GameDirection,
+               personality: String, style: String) -> GameThreat {
+        GameThreat { position, direction, personality, style }
     }
 }
 impl GameThreat {
-    fn to_threat(&self) -> Threat {
+    fn to_threat(&self) -> PyResult<Threat> {
         let mut t = Threat::spawn(
             self.position.to_pos(), from_style_name(&self.style));
         t.dir = self.direction.to_move();
-        t
+        t.personality = from_personality_name(&self.personality)?;
+        Ok(t)
     }
 }
 
@@ -162,14 +179,18 @@ impl GameState {
 }
 
 impl GameState {
-    fn to_game(&self) -> Game {
-        Game {
+    fn to_game(&self) -> PyResult<Game> {
+        let (width, height) = grid_dims(self.map.width as usize, self.map.height as usize)
+            .ok_or_else(|| PyValueError::new_err(format!(
+                "Map is {}x{}, max {}x{}", self.map.width, self.map.height, MAX_GRID_SIZE,
+                MAX_GRID_SIZE)))?;
+        Ok(Game {
             tick: self.tick as usize,
             pos: self.position.to_pos(),
-            grid: Grid::new(self.map.width as u8, self.map.height as u8, self.map.tiles.clone()),
-            threats: self.threats.iter().map(|t| t.to_threat()).collect(),
+            grid: Grid::new(width, height, self.map.tiles.clone()),
+            threats: self.threats.iter().map(|t| t.to_threat()).collect::<PyResult<_>>()?,
             alive: self.alive,
-        }
+        })
     }
 }
 
@@ -180,32 +201,32 @@ pub struct DevnullBot {
 #[pymethods]
 impl DevnullBot {
     pub fn pick_action(&mut self, game_state: &GameState) -> PyResult<Action> {
-        Ok(from_move(self.bot.pick_move(&game_state.to_game())))
+        Ok(from_move(self.bot.pick_move(&game_state.to_game()?)))
     }
 
     /// Replay an action after seeing a state. For offline replay.
     pub fn simulate(&mut self, game_state: &GameState, action: Action) -> PyResult<()> {
-        self.bot.simulate(&game_state.to_game(), action.to_move());
+        self.bot.simulate(&game_state.to_game()?, action.to_move());
         Ok(())
     }
 
     /// Replay a "move-to" action after seeing a state. For offline replay.
     pub fn simulate_move_to(&mut self, game_state: &GameState, move_to: &GamePosition) -> PyResult<()> {
-        self.bot.simulate_move_to(&game_state.to_game(), &move_to.to_pos());
+        self.bot.simulate_move_to(&game_state.to_game()?, &move_to.to_pos());
         Ok(())
     }
 }
 
 fn from_style_name(style: &str) -> Style {
-    match style {
-        "goldfish" => Style::Goldfish,
-        "bull" => Style::Bull,
-        "shark" => Style::Shark,
-        "owl" => Style::Owl,
-        "deer" => Style::Deer,
-        "hawk" => Style::Hawk,
-        &_ => panic!("Unsupported style name: {}", style),
-    }
+    Style::from_name(style).unwrap_or_else(|| {
+        println!("Unsupported style name: {}, will try to identify it.", style);
+        Style::Unknown
+    })
+}
+
+fn from_personality_name(personality: &str) -> PyResult<Personality> {
+    Personality::from_name(personality).ok_or_else(|| PyValueError::new_err(
+        format!("Unsupported personality name: {}", personality)))
 }
 
 fn from_move(m: Option<Move>) -> Action {
@@ -220,57 +241,34 @@ fn from_move(m: Option<Move>) -> Action {
     }
 }
 
-/// Verbose check whether an observed game state is a given known map.
-fn check_is_same_map(map: &Map, game_state: &GameState) -> bool {
-    // The following is needlessly thorough/verbose, but helps find bugs in map
-    // loading logic.
-    println!("Is it map {}?", map.name);
-    let game = game_state.to_game();
-    // Catch potential init issues
-    assert_eq!(map.game.tick, game.tick);
-    assert_eq!(map.game.alive, game.alive);
-
-    println!("Pos: {:?} =? {:?}", map.game.pos, game.pos);
-    if map.game.pos != game.pos { return false; }
-
-    println!("Grid dims: {:?} =? {:?}", map.game.grid.dims(), game.grid.dims());
-    if map.game.grid.dims() != game.grid.dims() { return false; }
-
-    println!("# threats: {} =? {}", map.game.threats.len(), game.threats.len());
-    if map.game.threats.len() != game.threats.len() { return false; }
-
-    // Check exact order of threats, too, just to ensure map loading is the
-    // exact same as the server.
-    for (i, (map_t, game_t)) in map.game.threats.iter().zip(game.threats.iter()).enumerate() {
-        println!("- Threat #{}:", i + 1);
-        println!("  - style: {:?} =? {:?}", map_t.style, game_t.style);
-        println!("  -   pos: {:?} =? {:?}", map_t.pos, game_t.pos);
-        println!("  -   dir: {:?} =? {:?}", map_t.dir, game_t.dir);
-        if map_t != game_t { return false; }
+/// Create a bot for the map matching 'game_state', or for a new map made from
+/// 'game_state' if we don't know it (saved to the maps folder if
+/// 'save_new_map' is set). If 'recover_desyncs' is set, mispredictions are
+/// reported and recovered from instead of crashing. Maps are loaded from
+/// 'maps_dir' if set (see 'map_loader::maps_dir' otherwise). 'bot' is a bot
+/// spec (see 'bot_spec'), the best bot if unset.
+#[pyfunction]
+#[pyo3(signature = (game_state, recover_desyncs=false, maps_dir=None, save_new_map=false,
+                    bot=None))]
+fn create_bot(game_state: &GameState, recover_desyncs: bool,
+              maps_dir: Option<PathBuf>, save_new_map: bool,
+              bot: Option<&str>) -> PyResult<DevnullBot> {
+    let spec = bot.map(BotSpec::parse).transpose()
+        .map_err(|e| PyValueError::new_err(format!("Invalid bot: {}", e)))?;
+    if maps_dir.is_some() {
+        map_loader::set_maps_dir(maps_dir);
     }
-
-    println!("layout =?");
-    if map.game.grid.tiles != game.grid.tiles {
-        println!("Map:");
-        debug_print(&map.game.grid, vec![]);
-        println!("Game:");
-        debug_print(&game.grid, vec![]);
-        return false;
+    let map = find_or_create_map(&game_state.to_game()?, save_new_map)
+        .map_err(|e| PyValueError::new_err(format!("Failed to load maps: {:?}", e)))?;
+    let state = State::new(map.game);
+    let mut bot = match spec {
+        Some(spec) => Bot::new(state, /*seed=*/42, spec),
+        None => Bot::new_best(state, /*seed=*/42),
+    };
+    if recover_desyncs {
+        bot.desync_handling = DesyncHandling::Resync;
     }
-
-    true
-}
-
-#[pyfunction]
-fn create_bot(game_state: &GameState) -> PyResult<DevnullBot> {
-    let maps = load_all_maps()
-        .map_err(|e| PyValueError::new_err(format!("Failed to load known maps: {:?}", e)))?;
-    let map = maps.into_iter().filter(|m| {
-        let is_same = check_is_same_map(m, game_state);
-        if !is_same { println!("Not that one!"); }
-        is_same
-    }).next().ok_or(PyValueError::new_err("Failed to find a matching map!"))?;
-    Ok(DevnullBot { bot: Bot::new_best(State::new(map.game), /*seed=*/42) })
+    Ok(DevnullBot { bot })
 }
 
 #[pymodule]
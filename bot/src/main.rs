@@ -1,23 +1,41 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::thread;
 use std::time::{Duration, Instant};
 use itertools::Itertools;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use num_traits::AsPrimitive;
 use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use statrs::distribution::{Binomial, Discrete};
 
+use devnull_bot::map_generator::{self, GeneratorOptions};
 use devnull_bot::map_loader;
-use devnull_bot::map_loader::{list_map_names, load_map, Map};
+use devnull_bot::map_loader::{load_all_maps, load_map, Map};
 use devnull_bot::mcts::Score;
-use devnull_bot::search::{Bot, BotName};
-use devnull_bot::simulation::{GameOptions, GameOverCheck, State};
+use devnull_bot::render::{self, RenderOptions};
+use devnull_bot::replay;
+use devnull_bot::grammar::{Expr, Heuristic, Policy};
+use devnull_bot::bot_spec::BotSpec;
+use devnull_bot::search::{Bot, BEST_BOT};
+use devnull_bot::simulation::{GameOptions, GameOverCheck, State, Style, TickSpeedMap};
+use devnull_bot::viewer::{self, SearchInfo, ViewerFrame};
 
 #[derive(Debug, Parser)]
 #[clap(name = "devnull")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[clap(flatten)]
     map_selection: MapSelectionArgGroup,
+    #[clap(flatten)]
+    map_filter: MapFilterArgs,
+    #[arg(long, help = "Where maps are. Defaults to '$DEVNULL_MAPS_DIR', or 'maps'.")]
+    maps_dir: Option<PathBuf>,
+    #[clap(flatten)]
+    generator: GeneratorArgs,
     #[arg(short, long, help = "Parallel evals. Warning: excessive parallelism can hurt search and underestimate scores.")]
     parallelism: Option<usize>,
     #[arg(long, help = "Print the tick number very N ticks, configured by this argument.")]
@@ -27,12 +45,195 @@ struct Cli {
     #[arg(long, default_value_t = true,
           help = "If we're allowed to jump over enemies on a single tick (for real game, this is true).")]
     allow_jump_over: std::primitive::bool,
+    #[arg(long, help = "Last tick of the game, after which the player wins. Defaults to the server's.")]
+    end_tick: Option<usize>,
+    #[arg(long, help = "Points given per tick survived. Defaults to the server's.")]
+    score_per_tick: Option<usize>,
+    #[arg(long, value_parser = parse_tick_speeds,
+          help = "Ticks between threat moves per personality, as 'lazy,tease,awake,hungry,insane' (e.g. '5,4,3,2,1'). Defaults to the server's.")]
+    tick_speeds: Option<TickSpeedMap>,
+    #[arg(long, help = "Start games at this tick instead of the map's.")]
+    starting_tick: Option<usize>,
     #[arg(long, help = "How many times to re-run each map, to average scores.")]
     samples: Option<usize>,
     #[arg(long, help = "Force this seed for all evaluations. If unset, pick based on run index.")]
     seed: Option<u64>,
     #[clap(flatten)]
     bot_selection: BotSelectionArgGroup,
+    #[clap(flatten)]
+    render: RenderArgs,
+    #[arg(long, conflicts_with = "parallelism",
+          help = "Step through each game in an interactive terminal viewer once it ends.")]
+    tui: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct RenderArgs {
+    #[arg(long, help = "Render games as GIFs in this directory (one per game).")]
+    render_dir: Option<PathBuf>,
+    #[arg(long, requires = "render_dir", help = "Render PNG frames (one directory per game) instead of GIFs.")]
+    render_png: bool,
+    #[arg(long, requires = "render_dir", help = "Show where Owls and Hawks are headed in renders.")]
+    render_targets: bool,
+    #[arg(long, requires = "render_dir", help = "Show where threats will be in the next N ticks in renders.")]
+    render_predicted_ticks: Option<usize>,
+}
+
+/// Where and how to render games.
+#[derive(Clone)]
+struct RenderPlan {
+    dir: PathBuf,
+    png: bool,
+    options: RenderOptions,
+}
+
+impl RenderPlan {
+    fn from_args(args: RenderArgs) -> Option<Self> {
+        let options = RenderOptions {
+            show_targets: args.render_targets,
+            predicted_ticks: args.render_predicted_ticks,
+            ..RenderOptions::default()
+        };
+        args.render_dir.map(|dir| Self { dir, png: args.render_png, options })
+    }
+
+    fn render(&self, name: &str, states: &[State]) {
+        let path = if self.png {
+            self.dir.join(name)
+        } else {
+            self.dir.join(format!("{}.gif", name))
+        };
+        match render::save(states, &path, &self.options) {
+            Ok(()) => println!("[{}] Rendered to {}", name, path.display()),
+            Err(e) => println!("[{}] Failed to render: {:?}", name, e),
+        }
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Replay games saved with 'SAVE_JSONL_PATH', report the first tick where
+    /// our simulation diverges from the server's.
+    /// Use '--render-dir' (before 'replay') to also render them, or '--tui' to
+    /// step through them.
+    Replay {
+        #[arg(required = true, help = "Saved games (.jsonl), e.g. games/*.jsonl")]
+        paths: Vec<PathBuf>,
+    },
+    /// Generate maps (see '--generate' options), save them in their own folder
+    /// to evaluate them like the server's maps.
+    GenerateMaps {
+        #[arg(long, help = "Where to save maps. Defaults to the maps directory.")]
+        out_dir: Option<PathBuf>,
+    },
+    /// Search for maps where the evaluated bot scores poorly, by repeatedly
+    /// mutating a map (walls, threat positions and styles) and keeping changes
+    /// that don't increase the bot's score. Starts from '--map', or a
+    /// generated map (see '--generate' options). Saves the worst maps found.
+    AdversarialSearch {
+        #[arg(long, default_value_t = 100, help = "Mutated maps to evaluate.")]
+        iterations: usize,
+        #[arg(long, default_value_t = 2, help = "Mutations applied to get each new map.")]
+        mutations: usize,
+        #[arg(long, default_value_t = 3, help = "How many of the worst maps to save.")]
+        keep: usize,
+        #[arg(long, help = "Where to save maps. Defaults to the maps directory.")]
+        out_dir: Option<PathBuf>,
+    },
+    /// Search for good algorithms in the Monte Carlo Search grammar (see
+    /// 'grammar'): evaluate algorithm expressions on the selected maps (like
+    /// evals, see '--map', '--all', '--generate' and '--samples') and rank
+    /// them by average score. Expressions are sampled from a search space of
+    /// common shapes and parameters, e.g.
+    /// 'step(repeat(100, select(ucb1(1.41), simulate(greedy(not_dead)))))'.
+    /// Other options (budget, evaluator, etc.) are the '--bot' spec's.
+    MetaSearch {
+        #[arg(long, default_value_t = 20,
+              help = "Expressions to sample from the search space (all of them if larger).")]
+        candidates: usize,
+        #[arg(long, help = "Also evaluate this expression (repeatable).")]
+        expr: Vec<Expr>,
+        #[arg(long, default_value_t = 10, help = "How many of the best expressions to show.")]
+        top: usize,
+    },
+    /// Tune the parameters of a bot (the '--bot' spec, the baseline): battle
+    /// variations of it (see '--exploration', '--iterations', etc.) against
+    /// it on the selected maps (like evals, see '--map', '--all', '--generate'
+    /// and '--samples'), and rank them by win rate, with the significance of
+    /// their wins.
+    Tune {
+        #[arg(long, value_enum, default_value_t = TuneStrategy::Grid)]
+        strategy: TuneStrategy,
+        #[clap(flatten)]
+        space: TuneSpace,
+        #[arg(long, default_value_t = 10, help = "Variations to sample with the random strategy.")]
+        candidates: usize,
+        #[arg(long, help = "Also write the ranking to this file.")]
+        report: Option<PathBuf>,
+    },
+    /// Check maps for problems: unknown pixel colors, multiple spawn points,
+    /// unreachable tiles, etc. Exits with an error if any are found.
+    Validate {
+        #[arg(help = "Map names or layout files ('.png'/'.txt'). If unset, checks all maps.")]
+        maps: Vec<String>,
+    },
+    /// Convert a map layout between image ('.png') and text ('.txt') formats,
+    /// e.g. 'maps/challenge1/layout.png' to 'maps/challenge1/layout.txt'.
+    ConvertMap {
+        input: PathBuf,
+        output: PathBuf,
+    },
+}
+
+/// How to pick the variations to battle when tuning.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum TuneStrategy {
+    /// Battle every variation.
+    Grid,
+    /// Battle a random sample of '--candidates' variations.
+    Random,
+    /// Successive halving: battle every variation, keep the best half, battle
+    /// them again with twice as many samples, until one is left.
+    Halving,
+}
+
+/// Values to try for each parameter when tuning, unset ones keep the
+/// baseline's.
+#[derive(Debug, clap::Args)]
+struct TuneSpace {
+    #[arg(long, value_delimiter = ',', help = "UCB-1 exploration parameters, e.g. '0.5,1,2'.")]
+    exploration: Vec<f32>,
+    #[arg(long, value_delimiter = ',', help = "Iterations per step, e.g. '50,100,200'.")]
+    iterations: Vec<usize>,
+    #[arg(long, value_delimiter = ',', help = "Rollout policies, e.g. 'random,greedy(show_off)'.")]
+    rollout: Vec<Policy>,
+    #[arg(long, value_delimiter = ',', help = "Evaluators, e.g. 'ticks_survived,not_dead'.")]
+    evaluator: Vec<Heuristic>,
+    #[arg(long, value_delimiter = ',', help = "Max rollout lengths, e.g. '50,100'.")]
+    max_rollout: Vec<usize>,
+}
+
+impl TuneSpace {
+    /// Every combination of values applied to 'baseline', without duplicates
+    /// (e.g. explorations of an algorithm without 'select').
+    fn variations(&self, baseline: &BotSpec) -> Vec<BotSpec> {
+        let options: Vec<Vec<String>> = [
+            self.exploration.iter().map(|c| format!("exploration={}", c)).collect(),
+            self.iterations.iter().map(|n| format!("iterations={}", n)).collect(),
+            self.rollout.iter().map(|p| format!("rollout={}", p)).collect(),
+            self.evaluator.iter().map(|h| format!("evaluator={}", h)).collect(),
+            self.max_rollout.iter().map(|n| format!("max-rollout={}", n)).collect(),
+        ].into_iter().filter(|values: &Vec<String>| !values.is_empty()).collect();
+        let mut variations: Vec<BotSpec> = Vec::new();
+        for combination in options.into_iter().multi_cartesian_product() {
+            let spec = BotSpec::parse(&format!("{},{}", baseline.name, combination.join(",")))
+                .expect("valid tuning options");
+            if variations.iter().all(|v| v.describe() != spec.describe()) {
+                variations.push(spec);
+            }
+        }
+        variations
+    }
 }
 
 #[derive(Debug, clap::Args)]
@@ -44,21 +245,100 @@ struct MapSelectionArgGroup {
     map_name: Option<String>,
 }
 
+#[derive(Debug, clap::Args)]
+struct MapFilterArgs {
+    #[arg(long, conflicts_with = "map_name",
+          help = "Only evaluate maps of this difficulty (from their meta.json), e.g. 'easy'.")]
+    difficulty: Option<String>,
+    #[arg(long, conflicts_with = "map_name",
+          help = "Only evaluate maps of this type (from their meta.json), e.g. 'challenge'.")]
+    map_type: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct GeneratorArgs {
+    #[arg(long, conflicts_with_all = ["map_name", "all"],
+          help = "Evaluate this many procedurally generated maps instead of the server's.")]
+    generate: Option<usize>,
+    #[arg(long, default_value_t = GeneratorOptions::default().width,
+          help = "Width of generated maps (odd).")]
+    gen_width: usize,
+    #[arg(long, default_value_t = GeneratorOptions::default().height,
+          help = "Height of generated maps (odd).")]
+    gen_height: usize,
+    #[arg(long, default_value_t = GeneratorOptions::default().corridor_density,
+          help = "Probability of opening walls between corridors in generated maps, for more loops.")]
+    gen_corridor_density: f64,
+    #[arg(long, default_value_t = GeneratorOptions::default().max_dead_ends,
+          help = "Most dead ends in generated maps.")]
+    gen_dead_ends: usize,
+    #[arg(long, value_parser = parse_styles,
+          help = "Threats of generated maps, as comma-separated styles (e.g. 'owl,owl,hawk'). Defaults to one of each.")]
+    gen_threats: Option<Vec<Style>>,
+    #[arg(long, default_value_t = 0, help = "Seed of the first generated map, others follow.")]
+    gen_seed: u64,
+}
+
+impl GeneratorArgs {
+    fn generate_maps(&self, count: usize) -> map_generator::Result<Vec<Map>> {
+        (0..count as u64).map(|i| {
+            map_generator::generate_map(&GeneratorOptions {
+                width: self.gen_width,
+                height: self.gen_height,
+                corridor_density: self.gen_corridor_density,
+                max_dead_ends: self.gen_dead_ends,
+                threats: self.gen_threats.clone()
+                    .unwrap_or_else(|| GeneratorOptions::default().threats),
+                seed: self.gen_seed + i,
+            })
+        }).collect()
+    }
+}
+
+impl MapFilterArgs {
+    fn matches(&self, map: &Map) -> bool {
+        self.difficulty.as_ref().is_none_or(|d| *d == map.meta.difficulty) &&
+            self.map_type.as_ref().is_none_or(|t| *t == map.meta.map_type)
+    }
+}
+
 #[derive(Debug, clap::Args)]
 #[group(multiple = false)]
 struct BotSelectionArgGroup {
-    #[arg(long, help = "Bot to evaluate. If unset, uses the best known algorithm.")]
-    bot: Option<BotName>,
+    #[arg(long, help = "Bot to evaluate, as a preset (e.g. 'show-off') or an algorithm \
+                         expression, optionally followed by options \
+                         (e.g. 'show-off,exploration=0.7,budget=150ms'). \
+                         If unset, uses the best known algorithm.")]
+    bot: Option<BotSpec>,
     #[clap(flatten)]
     battle: Option<BotBattleArgGroup>,
 }
 
 #[derive(Debug, clap::Args)]
 struct BotBattleArgGroup {
-    #[clap(long, help = "Bot to compare to 'right'.", requires = "right")]
-    left: Option<BotName>,
-    #[clap(long, help = "Bot to compare to 'left'.", requires = "left")]
-    right: Option<BotName>,
+    #[clap(long, help = "Bot to compare to 'right' (same format as '--bot').", requires = "right")]
+    left: Option<BotSpec>,
+    #[clap(long, help = "Bot to compare to 'left' (same format as '--bot').", requires = "left")]
+    right: Option<BotSpec>,
+}
+
+fn parse_tick_speeds(s: &str) -> Result<TickSpeedMap, String> {
+    let speeds: Vec<usize> = s.split(',')
+        .map(|v| v.trim().parse::<usize>().map_err(|e| format!("{}: '{}'", e, v)))
+        .collect::<Result<_, _>>()?;
+    if speeds.contains(&0) {
+        return Err("tick speeds must be at least 1".to_string());
+    }
+    let [lazy, tease, awake, hungry, insane] = speeds[..] else {
+        return Err(format!("expected 5 comma-separated values, got {}", speeds.len()));
+    };
+    Ok(TickSpeedMap { lazy, tease, awake, hungry, insane })
+}
+
+fn parse_styles(s: &str) -> Result<Vec<Style>, String> {
+    s.split(',')
+        .map(|name| Style::from_name(name.trim()).ok_or(format!("unknown style '{}'", name)))
+        .collect()
 }
 
 fn median<T: std::cmp::Ord + AsPrimitive<f32>>(mut values: Vec<T>) -> f32 {
@@ -74,8 +354,8 @@ fn median<T: std::cmp::Ord + AsPrimitive<f32>>(mut values: Vec<T>) -> f32 {
 /// What type of eval are we running.
 #[derive(Clone)]
 enum EvalType {
-    Solo { name: Option<BotName> },
-    Battle { left: BotName, right: BotName },
+    Solo { name: Option<BotSpec> },
+    Battle { left: BotSpec, right: BotSpec },
 }
 
 #[derive(Clone, Debug, PartialEq, Copy)]
@@ -92,11 +372,15 @@ struct EvalPlan {
     show_progress: Option<usize>,
     show_new_best_outcome: bool,
     eval: EvalType,
+    render: Option<RenderPlan>,
+    tui: bool,
 }
 
 #[derive(Clone)]
 struct EvalInfo {
     name: String,
+    /// Category of the map, see 'MapMeta::category'.
+    category: String,
     ticks: usize,
     eval: EvalType,
 }
@@ -146,22 +430,28 @@ impl EvalResults {
         }
     }
     fn name(&self) -> &String {
+        &self.info().name
+    }
+    fn info(&self) -> &EvalInfo {
         match self {
-            Self::Solo { info, .. } => &info.name,
-            Self::Battle { info, .. } => &info.name,
+            Self::Solo { info, .. } => info,
+            Self::Battle { info, .. } => info,
         }
     }
 }
 
-fn load_eval_maps(selection: MapSelectionArgGroup) -> map_loader::Result<Vec<Map>> {
-    let map_names = list_map_names()?;
+fn load_eval_maps(selection: MapSelectionArgGroup,
+                  filter: &MapFilterArgs) -> map_loader::Result<Vec<Map>> {
+    if let Some(name) = selection.map_name {
+        return Ok(vec![load_map(&name)?]);
+    }
+    let maps: Vec<Map> = load_all_maps()?.into_iter()
+        .filter(|map| filter.matches(map)).collect();
+    assert!(!maps.is_empty(), "no maps matching {:?}", filter);
     if selection.all {
-        return map_names.into_iter().map(|name| load_map(&name)).collect()
+        return Ok(maps);
     }
-    let name = selection.map_name.or_else(|| {
-        map_names.choose(&mut rand::thread_rng()).cloned()
-    }).expect("no maps loaded");
-    Ok(vec![load_map(&name)?])
+    Ok(vec![maps.choose(&mut rand::thread_rng()).cloned().unwrap()])
 }
 
 fn evaluate_map(plan: EvalPlan, seed: u64,
@@ -185,6 +475,8 @@ fn evaluate_map(plan: EvalPlan, seed: u64,
         }
     };
     assert!(bots.len() <= 2, "only support 1 or 2 bots, early exits on first fail");
+    let record_frames = plan.render.is_some() || plan.tui;
+    let mut trajectories: Vec<Vec<ViewerFrame>> = vec![Vec::new(); bots.len()];
     let mut tick_times = Vec::new();
     let mut num_evals = Vec::new();
     let mut best_outcome_seen = vec![Score::MIN; bots.len()];
@@ -193,25 +485,52 @@ fn evaluate_map(plan: EvalPlan, seed: u64,
         (!is_battle || is_win.iter().all(|w| !w)) {
         for (i, bot) in bots.iter_mut().enumerate() {
             if plan.show_progress.is_some_and(|n| bot.algorithm.state.tick % n == 0) {
-                println!("[{:?}][{}] tick {}", bot.name, plan.map.name,
+                println!("[{}][{}] tick {}", bot.spec, plan.map.name,
                          bot.algorithm.state.tick);
             }
+            let state_before = record_frames.then(|| bot.algorithm.state.clone());
             let time = Instant::now();
             let results = bot.self_play_tick();
+            if let Some(state) = state_before {
+                trajectories[i].push(ViewerFrame {
+                    search: Some(SearchInfo::from_results(&results)),
+                    ..ViewerFrame::new(state)
+                });
+            }
 
             is_win[i] = results.is_win;
             let stats = results.stats;
             if plan.show_new_best_outcome && stats.highest_score_seen > best_outcome_seen[i] {
                 best_outcome_seen[i] = stats.highest_score_seen;
-                println!("[{:?}][{}] new best: {}", bot.name, plan.map.name,
+                println!("[{}][{}] new best: {}", bot.spec, plan.map.name,
                          best_outcome_seen[i]);
             }
             tick_times.push(time.elapsed());
             num_evals.push(stats.num_evals);
         }
     }
+    if record_frames {
+        for (i, (bot, frames)) in bots.iter().zip(trajectories.iter_mut()).enumerate() {
+            frames.push(ViewerFrame::new(bot.algorithm.state.clone()));
+            let name = if is_battle {
+                format!("{}_{}_{}{}", plan.map.name, seed,
+                        if i == 0 { "left_" } else { "right_" }, bot.spec)
+            } else {
+                format!("{}_{}", plan.map.name, seed)
+            };
+            if let Some(render) = &plan.render {
+                let states: Vec<State> = frames.iter().map(|f| f.state.clone()).collect();
+                render.render(&name, &states);
+            }
+            if plan.tui {
+                view(&name, frames);
+            }
+        }
+    }
     let ticks = bots.iter().map(|bot| bot.algorithm.state.tick).min().unwrap();
-    let info = EvalInfo { name: plan.map.name, ticks, eval: plan.eval };
+    let info = EvalInfo {
+        name: plan.map.name, category: plan.map.meta.category(), ticks, eval: plan.eval,
+    };
     let results = match info.eval {
         EvalType::Solo { .. } => EvalResults::new_solo_results(
             info, bots[0].algorithm.state.score(), tick_times, num_evals),
@@ -243,7 +562,8 @@ fn evaluate_map(plan: EvalPlan, seed: u64,
 /// Plan what evals to run (how many repeats, what bots, etc.).
 fn plan_evals(eval_type: EvalType, num_samples: usize, loaded_maps: &Vec<Map>,
               show_progress_every_n: Option<usize>,
-              show_new_best_outcome: bool) -> Vec<EvalPlan> {
+              show_new_best_outcome: bool,
+              render: Option<RenderPlan>, tui: bool) -> Vec<EvalPlan> {
     loaded_maps.into_iter()
         .flat_map(|map| std::iter::repeat(map).take(num_samples))
         .map(|map| {
@@ -252,6 +572,8 @@ fn plan_evals(eval_type: EvalType, num_samples: usize, loaded_maps: &Vec<Map>,
                 map: map.clone(),
                 show_progress: show_progress_every_n,
                 show_new_best_outcome,
+                render: render.clone(),
+                tui,
             }
         }).collect()
 }
@@ -309,14 +631,14 @@ fn compute_p_value(left_wins: usize, ties: usize, right_wins: usize) -> f64 {
 /// Qualify the statistical significance of left/right wins/losses/ties, in
 /// natural language.
 fn qualify_significance(left_wins: usize, ties: usize, right_wins: usize,
-                        left: BotName, right: BotName) -> String {
+                        left: &BotSpec, right: &BotSpec) -> String {
     const ALPHA: f64 = 0.05;  // False positives accepted
     let p_value = compute_p_value(left_wins, ties, right_wins);
     if p_value <= ALPHA {
         if left_wins > right_wins {
-            format!("{:?} better than {:?} (p-value={:.3})", left, right, p_value)
+            format!("{} better than {} (p-value={:.3})", left, right, p_value)
         } else {
-            format!("{:?} better than {:?} (p-value={:.3})", right, left, p_value)
+            format!("{} better than {} (p-value={:.3})", right, left, p_value)
         }
     } else {
         format!("not significant (p-value={:.3})", p_value)
@@ -325,6 +647,7 @@ fn qualify_significance(left_wins: usize, ties: usize, right_wins: usize,
 
 /// Aggregated per-map results for an eval type.
 /// Note: only some fields are set, depending on eval type.
+#[derive(Default)]
 struct SummaryResults {
     // Solo evals
     scores: Vec<usize>,
@@ -334,16 +657,36 @@ struct SummaryResults {
     ties: usize,
 }
 
+impl SummaryResults {
+    fn merge(&mut self, other: &SummaryResults) {
+        self.scores.extend(&other.scores);
+        self.left_wins += other.left_wins;
+        self.right_wins += other.right_wins;
+        self.ties += other.ties;
+    }
+}
+
 fn show_results(eval: EvalType, results: Vec<EvalResults>) {
     if results.len() > 1 {  // Don't show summary for single evals
-        let mut summary_results = SummaryResults {
-            scores: Vec::new(), left_wins: 0, right_wins: 0, ties: 0,
-        };
+        let mut summary_results = SummaryResults::default();
+        let mut category_results: BTreeMap<String, SummaryResults> = BTreeMap::new();
         println!("\n\n[SUMMARY]");
         for (name, results) in &results.iter()
             .sorted_by(|a, b| a.name().cmp(&b.name())).chunk_by(|r| r.name()) {
-            let map_results = results.cloned().collect();
-            show_map_results(eval.clone(), name, map_results, &mut summary_results);
+            let map_results: Vec<EvalResults> = results.cloned().collect();
+            let category = map_results[0].info().category.clone();
+            let mut map_summary = SummaryResults::default();
+            show_map_results(eval.clone(), name, map_results, &mut map_summary);
+            summary_results.merge(&map_summary);
+            category_results.entry(category).or_default().merge(&map_summary);
+        }
+        // Only worth breaking down if maps are of different kinds.
+        if category_results.len() > 1 {
+            for (category, summary) in category_results {
+                println!("\n[{}]", category);
+                show_summary_results(eval.clone(), summary);
+            }
+            println!("\n[all]");
         }
         show_summary_results(eval, summary_results);
     }
@@ -378,9 +721,9 @@ fn show_map_results(eval: EvalType, map_name: &String,
                 }}).collect();
             if map_winners.len() == 1 {
                 let message = match map_winners[0] {
-                    Winner::Left => format!("{:?} won", left),
+                    Winner::Left => format!("{} won", left),
                     Winner::Tie => "tie".to_string(),
-                    Winner::Right => format!("{:?} won", right),
+                    Winner::Right => format!("{} won", right),
                 };
                 println!("[{}]: {} ", map_name, message);
             } else {
@@ -390,8 +733,8 @@ fn show_map_results(eval: EvalType, map_name: &String,
                     .filter(|&&w| w == Winner::Right).count();
                 let ties = map_winners.len() - left_wins - right_wins;
                 let significance = qualify_significance(
-                    left_wins, ties, right_wins, left, right);
-                println!("[{}]: {} {:?} wins, {} ties, {} {:?} wins -- {}",
+                    left_wins, ties, right_wins, &left, &right);
+                println!("[{}]: {} {} wins, {} ties, {} {} wins -- {}",
                          map_name, left_wins, left, ties, right_wins, right,
                          significance);
                 summary.left_wins += left_wins;
@@ -417,17 +760,269 @@ fn show_summary_results(eval: EvalType, summary: SummaryResults) {
         },
         EvalType::Battle { left, right } => {
             let significance = qualify_significance(
-                summary.left_wins, summary.ties, summary.right_wins, left,
-                right);
-            println!("{:?}: {} wins, ties: {}, {:?}: {} wins -- {}", left,
+                summary.left_wins, summary.ties, summary.right_wins, &left,
+                &right);
+            println!("{}: {} wins, ties: {}, {}: {} wins -- {}", left,
                      summary.left_wins, summary.ties, right, summary.right_wins,
                      significance);
         },
     }
 }
 
+/// Average score of the evaluated bot on a map.
+fn average_score(map: &Map, eval_type: &EvalType, samples: usize, parallelism: usize,
+                 game_options: &GameOptions) -> f32 {
+    let evals = plan_evals(eval_type.clone(), samples, &vec![map.clone()], None, false,
+                           None, false);
+    let results = run_evals(evals, parallelism, None, game_options);
+    let scores: Vec<usize> = results.iter().map(|r| match r {
+        EvalResults::Solo { score, .. } => *score,
+        _ => panic!("average scores only support solo evals"),
+    }).collect();
+    scores.iter().sum::<usize>() as f32 / scores.len() as f32
+}
+
+/// Hill-climb map mutations to minimize 'score_map', returns the worst (lowest
+/// score) distinct mutated maps seen, worst first.
+fn adversarial_search(start: Map, score_map: impl Fn(&Map) -> f32, seed: u64,
+                      iterations: usize, mutations: usize, keep: usize) -> Vec<(f32, Map)> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut current_score = score_map(&start);
+    println!("[adversarial] start ({}): score {:.1}", start.name, current_score);
+    let mut current = start;
+    let mut worst: Vec<(f32, Map)> = Vec::new();
+    for iteration in 1..=iterations {
+        let mut candidate = current.clone();
+        for _ in 0..mutations {
+            candidate = map_generator::mutate_map(&candidate, &mut rng);
+        }
+        candidate.name = format!("adversarial_{}_{}", seed, iteration);
+        candidate.meta.map_type = "adversarial".to_string();
+        let score = score_map(&candidate);
+        println!("[adversarial] iteration {}/{}: score {:.1} (current: {:.1})",
+                 iteration, iterations, score, current_score);
+        if worst.iter().all(|(_, map)| map.game != candidate.game) {
+            worst.push((score, candidate.clone()));
+            worst.sort_by(|a, b| a.0.total_cmp(&b.0));
+            worst.truncate(keep);
+        }
+        // Also accept ties, to drift across plateaus.
+        if score <= current_score {
+            current = candidate;
+            current_score = score;
+        }
+    }
+    worst
+}
+
+/// Expressions to evaluate in a meta search: 'extra' ones, then up to 'count'
+/// others sampled from the search space.
+fn meta_search_candidates(count: usize, extra: Vec<Expr>, seed: u64) -> Vec<Expr> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut candidates = extra;
+    for expr in Expr::search_space().choose_multiple(&mut rng, count) {
+        if !candidates.contains(expr) {
+            candidates.push(expr.clone());
+        }
+    }
+    candidates
+}
+
+/// Score each expression on all maps with 'score_map', returns (average
+/// score, per map scores, expression), best (highest average) first.
+fn meta_search(candidates: Vec<Expr>, maps: &[Map],
+               score_map: impl Fn(&Expr, &Map) -> f32) -> Vec<(f32, Vec<f32>, Expr)> {
+    let num_candidates = candidates.len();
+    let mut ranking = Vec::new();
+    for (i, expr) in candidates.into_iter().enumerate() {
+        let map_scores: Vec<f32> = maps.iter().map(|map| score_map(&expr, map)).collect();
+        let score = map_scores.iter().sum::<f32>() / map_scores.len() as f32;
+        println!("[meta] candidate {}/{}: score {:.1} -- {}", i + 1, num_candidates, score, expr);
+        ranking.push((score, map_scores, expr));
+    }
+    // Stable, ties keep the order candidates were given in.
+    ranking.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranking
+}
+
+/// Battles of a variation against the baseline when tuning.
+struct TuneResult {
+    spec: BotSpec,
+    wins: usize,
+    ties: usize,
+    losses: usize,
+}
+
+impl TuneResult {
+    /// Ties count as half a win.
+    fn win_rate(&self) -> f32 {
+        (self.wins as f32 + self.ties as f32 / 2.0) / (self.wins + self.ties + self.losses) as f32
+    }
+}
+
+/// Battle each variation against the baseline with 'battle', returns their
+/// results, best (highest win rate) first.
+fn battle_variations(variations: Vec<BotSpec>,
+                     battle: impl Fn(&BotSpec) -> Vec<Winner>) -> Vec<TuneResult> {
+    let num_variations = variations.len();
+    let mut results: Vec<TuneResult> = variations.into_iter().enumerate().map(|(i, spec)| {
+        let winners = battle(&spec);
+        let wins = winners.iter().filter(|&&w| w == Winner::Left).count();
+        let losses = winners.iter().filter(|&&w| w == Winner::Right).count();
+        let result = TuneResult { spec, wins, ties: winners.len() - wins - losses, losses };
+        println!("[tune] variation {}/{}: win rate {:.1}% -- {}", i + 1, num_variations,
+                 result.win_rate() * 100.0, result.spec);
+        result
+    }).collect();
+    // Stable, ties keep the order variations were given in.
+    results.sort_by(|a, b| b.win_rate().total_cmp(&a.win_rate()));
+    results
+}
+
+/// Rank variations against the baseline with a strategy, best first.
+/// 'battle' plays a variation against the baseline 'samples' times per map.
+fn tune(strategy: TuneStrategy, variations: Vec<BotSpec>, candidates: usize, seed: u64,
+        samples: usize, battle: impl Fn(&BotSpec, usize) -> Vec<Winner>) -> Vec<TuneResult> {
+    match strategy {
+        TuneStrategy::Grid => battle_variations(variations, |spec| battle(spec, samples)),
+        TuneStrategy::Random => {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let sampled = variations.choose_multiple(&mut rng, candidates).cloned().collect();
+            battle_variations(sampled, |spec| battle(spec, samples))
+        },
+        TuneStrategy::Halving => {
+            let mut remaining = variations;
+            let mut samples = samples;
+            // Variations eliminated by each round, ranked after later rounds'.
+            let mut eliminated: Vec<Vec<TuneResult>> = Vec::new();
+            loop {
+                println!("[tune] halving round with {} variations, {} samples per map",
+                         remaining.len(), samples);
+                let mut results = battle_variations(remaining, |spec| battle(spec, samples));
+                if results.len() <= 1 {
+                    eliminated.push(results);
+                    break;
+                }
+                let losers = results.split_off(results.len().div_ceil(2));
+                eliminated.push(losers);
+                remaining = results.into_iter().map(|result| result.spec).collect();
+                samples *= 2;
+            }
+            eliminated.into_iter().rev().flatten().collect()
+        },
+    }
+}
+
+/// Ranking of tuned variations, as text.
+fn tune_report(baseline: &BotSpec, maps: &[Map], ranking: &[TuneResult]) -> String {
+    let mut report = format!("Baseline: {} ({})\nMaps: {:?}\n", baseline, baseline.describe(),
+                             maps.iter().map(|map| &map.name).collect::<Vec<_>>());
+    for (rank, result) in ranking.iter().enumerate() {
+        let significance = qualify_significance(result.wins, result.ties, result.losses,
+                                                &result.spec, baseline);
+        report += &format!("{}. Win rate: {:.1}% ({} wins, {} ties, {} losses) -- {}\n   {}\n",
+                           rank + 1, result.win_rate() * 100.0, result.wins, result.ties,
+                           result.losses, significance, result.spec.describe());
+    }
+    report
+}
+
+/// Validate maps (names or layout files), returns whether they are all valid.
+fn validate_maps(maps: Vec<String>) -> bool {
+    let maps = if maps.is_empty() {
+        map_loader::list_map_names().expect("Error listing maps")
+    } else {
+        maps
+    };
+    let mut all_valid = true;
+    for map in maps {
+        let path = if map.ends_with(".png") || map.ends_with(".txt") {
+            PathBuf::from(&map)
+        } else {
+            map_loader::layout_path(&map)
+        };
+        let issues = if path.exists() {
+            map_loader::validate_layout(&path)
+        } else {
+            // e.g. embedded maps, check what we can once loaded.
+            load_map(&map).map(|map| map_loader::validate_game(&map.game))
+        };
+        match issues {
+            Ok(issues) if issues.is_empty() => println!("[{}] OK", map),
+            Ok(issues) => {
+                for issue in issues {
+                    println!("[{}] {}", map, issue);
+                }
+                all_valid = false;
+            },
+            Err(e) => {
+                println!("[{}] Failed to validate: {:?}", map, e);
+                all_valid = false;
+            },
+        }
+    }
+    all_valid
+}
+
+/// Replay saved games, returns whether they all matched our simulation.
+fn replay_games(paths: &[PathBuf], game_options: &GameOptions,
+                render: Option<RenderPlan>, tui: bool) -> bool {
+    let mut all_match = true;
+    for path in paths {
+        let name = path.display();
+        let ticks = replay::load_replay(path);
+        if let (true, Ok(ticks)) = (render.is_some() || tui, &ticks) {
+            let stem = path.file_stem().map_or("replay".into(), |s| s.to_string_lossy());
+            match replay::replay_states(ticks, game_options.clone()) {
+                Ok(states) => {
+                    if let Some(render) = &render {
+                        let states: Vec<State> = states.iter().map(|s| s.state.clone()).collect();
+                        render.render(&stem, &states);
+                    }
+                    if tui {
+                        let frames: Vec<ViewerFrame> = states.into_iter().map(|s| ViewerFrame {
+                            notes: s.mispredictions.iter()
+                                .map(|m| format!("Misprediction: {}", m)).collect(),
+                            ..ViewerFrame::new(s.state)
+                        }).collect();
+                        view(&stem, &frames);
+                    }
+                },
+                Err(e) => println!("[{}] Failed to replay states: {:?}", name, e),
+            }
+        }
+        let report = ticks.and_then(|ticks| replay::replay(&ticks, game_options.clone()));
+        match report {
+            Ok(replay::ReplayReport { ticks_matched, divergence: None }) => {
+                println!("[{}] OK, {} ticks match", name, ticks_matched);
+            },
+            Ok(replay::ReplayReport { ticks_matched, divergence: Some(divergence) }) => {
+                println!("[{}] Diverged on tick {} (after {} matching ticks):",
+                         name, divergence.tick, ticks_matched);
+                for misprediction in &divergence.mispredictions {
+                    println!("[{}]   {}", name, misprediction);
+                }
+                all_match = false;
+            },
+            Err(e) => {
+                println!("[{}] Failed to replay: {:?}", name, e);
+                all_match = false;
+            },
+        }
+    }
+    all_match
+}
+
+/// Step through a game in the terminal viewer.
+fn view(name: &str, frames: &[ViewerFrame]) {
+    if let Err(e) = viewer::run(name, frames) {
+        println!("[{}] Failed to run viewer: {:?}", name, e);
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
+    map_loader::set_maps_dir(cli.maps_dir.clone());
     let repeats = cli.samples.unwrap_or(1);
     let parallelism = cli.parallelism.unwrap_or(1);
     // Detect potentially excessive parallelism and warn user.
@@ -447,7 +1042,7 @@ fn main() {
             right: battle.right.expect("missing right"),
         }
     } else {
-        EvalType::Solo { name: cli.bot_selection.bot }
+        EvalType::Solo { name: cli.bot_selection.bot.clone() }
     };
     let mut game_options = GameOptions::default();
     if cli.allow_jump_over {
@@ -456,10 +1051,130 @@ fn main() {
     } else {
         game_options.game_over = GameOverCheck::StartOfTickAndAfterPlayerMove;
     }
+    if let Some(end_tick) = cli.end_tick {
+        game_options.end_tick = end_tick;
+    }
+    if let Some(score_per_tick) = cli.score_per_tick {
+        game_options.score_per_tick = score_per_tick;
+    }
+    if let Some(tick_speeds) = cli.tick_speeds {
+        game_options.tick_speeds = tick_speeds;
+    }
+    game_options.starting_tick = cli.starting_tick;
+    let render = RenderPlan::from_args(cli.render);
+
+    match cli.command {
+        Some(Command::Replay { paths }) => {
+            if !replay_games(&paths, &game_options, render, cli.tui) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::GenerateMaps { out_dir }) => {
+            let out_dir = out_dir.unwrap_or_else(map_loader::maps_dir);
+            let maps = cli.generator.generate_maps(cli.generator.generate.unwrap_or(1))
+                .expect("Error generating maps");
+            for map in maps {
+                map_loader::save_map(&map, &out_dir).expect("Error saving map");
+                println!("Saved {}", out_dir.join(&map.name).display());
+            }
+            return;
+        },
+        Some(Command::AdversarialSearch { iterations, mutations, keep, out_dir }) => {
+            if matches!(eval_type, EvalType::Battle { .. }) {
+                Cli::command().error(clap::error::ErrorKind::ArgumentConflict,
+                                     "adversarial-search scores maps with '--bot', not '--left/--right'")
+                    .exit();
+            }
+            let out_dir = out_dir.unwrap_or_else(map_loader::maps_dir);
+            let start = if let Some(name) = cli.map_selection.map_name {
+                load_map(&name).expect("Error loading map")
+            } else {
+                cli.generator.generate_maps(1).expect("Error generating map").remove(0)
+            };
+            let score_map = |map: &Map| {
+                average_score(map, &eval_type, repeats, parallelism, &game_options)
+            };
+            let worst = adversarial_search(start, score_map, fixed_seed.unwrap_or(0),
+                                           iterations, mutations, keep);
+            for (score, map) in worst {
+                map_loader::save_map(&map, &out_dir).expect("Error saving map");
+                println!("Saved {} (score {:.1})", out_dir.join(&map.name).display(), score);
+            }
+            return;
+        },
+        Some(Command::MetaSearch { candidates, expr, top }) => {
+            let maps = if let Some(count) = cli.generator.generate {
+                cli.generator.generate_maps(count).expect("Error generating maps")
+            } else {
+                load_eval_maps(cli.map_selection, &cli.map_filter).expect("Error loading map")
+            };
+            let candidates = meta_search_candidates(candidates, expr, fixed_seed.unwrap_or(0));
+            // Options other than the algorithm come from '--bot', if set.
+            let base = cli.bot_selection.bot.unwrap_or_else(|| BotSpec::preset(BEST_BOT));
+            let score_map = |expr: &Expr, map: &Map| {
+                let eval_type = EvalType::Solo { name: Some(base.with_algorithm(expr.clone())) };
+                average_score(map, &eval_type, repeats, parallelism, &game_options)
+            };
+            let ranking = meta_search(candidates, &maps, score_map);
+            println!("\n\n[RANKING]");
+            println!("Maps: {:?}", maps.iter().map(|map| &map.name).collect::<Vec<_>>());
+            for (rank, (score, map_scores, expr)) in ranking.iter().take(top).enumerate() {
+                println!("{}. Avg score: {:.1}  {}", rank + 1, score, expr);
+                println!("   Per map scores: {:?}",
+                         map_scores.iter().map(|s| s.round() as usize).collect::<Vec<_>>());
+            }
+            return;
+        },
+        Some(Command::Tune { strategy, space, candidates, report }) => {
+            let maps = if let Some(count) = cli.generator.generate {
+                cli.generator.generate_maps(count).expect("Error generating maps")
+            } else {
+                load_eval_maps(cli.map_selection, &cli.map_filter).expect("Error loading map")
+            };
+            let baseline = cli.bot_selection.bot.unwrap_or_else(|| BotSpec::preset(BEST_BOT));
+            let variations = space.variations(&baseline);
+            assert!(!variations.is_empty(), "nothing to tune, see 'tune --help' for parameters");
+            let battle = |spec: &BotSpec, samples: usize| {
+                let eval_type = EvalType::Battle { left: spec.clone(), right: baseline.clone() };
+                let evals = plan_evals(eval_type, samples, &maps, None, false, None, false);
+                run_evals(evals, parallelism, None, &game_options).iter().map(|r| match r {
+                    EvalResults::Battle { winner, .. } => *winner,
+                    _ => panic!("wrong results for eval type battle"),
+                }).collect()
+            };
+            let ranking = tune(strategy, variations, candidates, fixed_seed.unwrap_or(0),
+                               repeats, battle);
+            let text = tune_report(&baseline, &maps, &ranking);
+            println!("\n\n[RANKING]\n{}", text);
+            if let Some(path) = report {
+                std::fs::write(&path, text).expect("Error writing report");
+                println!("Saved report to {}", path.display());
+            }
+            return;
+        },
+        Some(Command::Validate { maps }) => {
+            if !validate_maps(maps) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::ConvertMap { input, output }) => {
+            let game = map_loader::load_layout(&input).expect("Error loading layout");
+            map_loader::save_layout(&game, &output).expect("Error saving layout");
+            println!("Converted {} to {}", input.display(), output.display());
+            return;
+        },
+        None => {},
+    }
 
-    let maps = load_eval_maps(cli.map_selection).expect("Error loading map");
+    let maps = if let Some(count) = cli.generator.generate {
+        cli.generator.generate_maps(count).expect("Error generating maps")
+    } else {
+        load_eval_maps(cli.map_selection, &cli.map_filter).expect("Error loading map")
+    };
     let evals = plan_evals(eval_type.clone(), repeats, &maps, show_progress,
-                           show_new_best_outcome);
+                           show_new_best_outcome, render, cli.tui);
     let results = run_evals(evals, parallelism, fixed_seed, &game_options);
     show_results(eval_type, results);
 }
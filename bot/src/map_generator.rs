@@ -0,0 +1,393 @@
+/// Generate Pac-Man-like maps (mazes mirrored left/right), to stress test bots
+/// beyond the few maps the server has.
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::grid::{grid_dims, Grid, Move, Pos};
+use crate::map_loader::{validate_game, Map, MapMeta};
+use crate::simulation::{Game, Style, Threat};
+
+/// 'type' of generated maps in their 'MapMeta'.
+pub const GENERATED_MAP_TYPE: &str = "generated";
+
+pub type Result<T> = std::result::Result<T, GeneratorError>;
+
+#[derive(Debug)]
+pub enum GeneratorError {
+    /// Dimensions must be odd (for corridors with walls around them), between
+    /// 5 and 'MAX_GRID_SIZE'.
+    InvalidSize { width: usize, height: usize },
+    /// Not enough room for all threats away from the spawn point.
+    TooManyThreats { threats: usize, available_tiles: usize },
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratorOptions {
+    /// Odd, between 5 and 'MAX_GRID_SIZE'.
+    pub width: usize,
+    /// Odd, between 5 and 'MAX_GRID_SIZE'.
+    pub height: usize,
+    /// Probability of opening each remaining wall between two corridors, after
+    /// generating the maze. 0 gives a perfect maze (a single path between any
+    /// two tiles), higher values give more loops to escape through.
+    pub corridor_density: f64,
+    /// Most dead ends to keep, others are opened to a neighboring corridor.
+    pub max_dead_ends: usize,
+    /// Style of each threat to spawn.
+    pub threats: Vec<Style>,
+    pub seed: u64,
+}
+
+impl Default for GeneratorOptions {
+    /// Similar to the server's challenge maps.
+    fn default() -> Self {
+        Self {
+            width: 21,
+            height: 15,
+            corridor_density: 0.3,
+            max_dead_ends: 4,
+            threats: Style::known().collect(),
+            seed: 0,
+        }
+    }
+}
+
+/// Generate a map, deterministically for given options.
+pub fn generate_map(options: &GeneratorOptions) -> Result<Map> {
+    let (width, height) = (options.width, options.height);
+    let dims = grid_dims(width, height)
+        .filter(|_| width >= 5 && height >= 5 && width % 2 == 1 && height % 2 == 1)
+        .ok_or(GeneratorError::InvalidSize { width, height })?;
+    let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+    let mut maze = Maze::new(width, height);
+    maze.carve_spanning_tree(&mut rng);
+    maze.connect_halves(&mut rng);
+    maze.open_walls(options.corridor_density, &mut rng);
+    maze.remove_dead_ends(options.max_dead_ends, &mut rng);
+
+    // Spawn in the center column, on the corridor closest to the middle.
+    let center_x = (width / 2) as i16;
+    let pos = maze.empty_tiles().into_iter()
+        .filter(|p| p.x == center_x)
+        .min_by_key(|p| (p.y - height as i16 / 2).abs())
+        .unwrap();
+    // Threats on the tiles furthest away from the spawn, so the player isn't
+    // immediately cornered.
+    let mut candidates: Vec<Pos> = maze.empty_tiles().into_iter()
+        .filter(|p| *p != pos).collect();
+    if candidates.len() < options.threats.len() {
+        return Err(GeneratorError::TooManyThreats {
+            threats: options.threats.len(), available_tiles: candidates.len(),
+        });
+    }
+    candidates.shuffle(&mut rng);
+    candidates.sort_by_key(|p| std::cmp::Reverse(p.manhattan_dist(&pos)));
+    let num_far_tiles = (options.threats.len() * 3).min(candidates.len());
+    let far_tiles = &mut candidates[..num_far_tiles];
+    far_tiles.shuffle(&mut rng);
+    let mut threat_positions: Vec<Pos> = far_tiles[..options.threats.len()].to_vec();
+    // Threats are spawned in reading order, like in map layouts.
+    threat_positions.sort_by_key(|p| (p.y, p.x));
+    let mut styles = options.threats.clone();
+    styles.shuffle(&mut rng);
+    let threats = threat_positions.into_iter().zip(styles)
+        .map(|(p, style)| Threat::spawn(p, style)).collect();
+
+    Ok(Map {
+        name: format!("generated_{}x{}_{}", width, height, options.seed),
+        game: Game {
+            tick: 1,
+            alive: true,
+            grid: Grid::new(dims.0, dims.1, maze.tiles),
+            pos,
+            threats,
+        },
+        meta: MapMeta {
+            map_type: GENERATED_MAP_TYPE.to_string(),
+            difficulty: "unknown".to_string(),
+        },
+    })
+}
+
+/// Randomly change a map a little: toggle a wall (mirrored left/right), move a
+/// threat, or change a threat's style. Mutated maps stay valid (see
+/// 'validate_game'), e.g. all empty tiles stay reachable.
+pub fn mutate_map(map: &Map, rng: &mut impl Rng) -> Map {
+    let game = &map.game;
+    let (width, height) = (game.grid.width as i16, game.grid.height as i16);
+    let threats: Vec<(Pos, Style)> = game.threats.iter().map(|t| (t.pos, t.style)).collect();
+    // Retry mutations that would make the map invalid.
+    for _ in 0..100 {
+        let mut tiles = game.grid.tiles.clone();
+        let mut new_threats = threats.clone();
+        let is_free = |p: &Pos| *p != game.pos && threats.iter().all(|(t, _)| t != p);
+        match rng.gen_range(0..3) {
+            0 => {
+                let pos = Pos { x: rng.gen_range(1..width - 1), y: rng.gen_range(1..height - 1) };
+                let mirror = Pos { x: width - 1 - pos.x, y: pos.y };
+                if !is_free(&pos) || !is_free(&mirror) { continue; }
+                let wall = !tiles[pos.x as usize][pos.y as usize];
+                tiles[pos.x as usize][pos.y as usize] = wall;
+                tiles[mirror.x as usize][mirror.y as usize] = wall;
+            },
+            1 if !threats.is_empty() => {
+                let pos = *game.grid.empty_tiles.choose(rng).unwrap();
+                if !is_free(&pos) { continue; }
+                new_threats.choose_mut(rng).unwrap().0 = pos;
+            },
+            2 if !threats.is_empty() => {
+                new_threats.choose_mut(rng).unwrap().1 =
+                    *Style::known().collect::<Vec<_>>().choose(rng).unwrap();
+            },
+            _ => continue,
+        }
+        let grid = Grid::new(width as u16, height as u16, tiles);
+        // Threats are spawned in reading order, like in map layouts.
+        new_threats.sort_by_key(|(p, _)| (p.y, p.x));
+        let threats = new_threats.into_iter().map(|(p, style)| Threat::spawn(p, style)).collect();
+        let mutated = Game { grid, threats, ..game.clone() };
+        // e.g. hawks panic without intersections to idle at.
+        if !validate_game(&mutated).is_empty() { continue; }
+        return Map { game: mutated, ..map.clone() };
+    }
+    map.clone()
+}
+
+/// Maze being carved. Corridor "cells" are at odd coordinates, with walls (or
+/// openings) between them. Everything is mirrored left/right as we carve.
+struct Maze {
+    width: usize,
+    height: usize,
+    /// Dims: [x][y], true for walls
+    tiles: Vec<Vec<bool>>,
+}
+
+impl Maze {
+    fn new(width: usize, height: usize) -> Self {
+        Self { width, height, tiles: vec![vec![true; height]; width] }
+    }
+
+    /// Cells on the left half, including the center column.
+    fn left_cells(&self) -> Vec<Pos> {
+        (1..=self.width as i16 / 2).step_by(2)
+            .flat_map(|x| (1..self.height as i16 - 1).step_by(2).map(move |y| Pos { x, y }))
+            .collect()
+    }
+
+    fn is_cell(&self, pos: &Pos) -> bool {
+        pos.x > 0 && pos.y > 0 && (pos.x as usize) < self.width - 1
+            && (pos.y as usize) < self.height - 1
+    }
+
+    /// Neighboring cells on the left half (or the mirror of 'cell', across
+    /// the center), with the direction to them.
+    fn left_neighbors(&self, cell: &Pos) -> Vec<(Move, Pos)> {
+        [Move::Up, Move::Down, Move::Left, Move::Right].into_iter()
+            .map(|m| (m, cell.moved(m).moved(m)))
+            .filter(|(_, p)| self.is_cell(p) && p.x <= self.width as i16 - 1 - cell.x)
+            .collect()
+    }
+
+    fn is_open(&self, pos: &Pos) -> bool {
+        !self.tiles[pos.x as usize][pos.y as usize]
+    }
+
+    fn open(&mut self, pos: &Pos) {
+        self.tiles[pos.x as usize][pos.y as usize] = false;
+        self.tiles[self.width - 1 - pos.x as usize][pos.y as usize] = false;
+    }
+
+    /// Open a cell, the wall in direction 'm' and the cell behind it.
+    fn carve(&mut self, cell: &Pos, m: Move) {
+        self.open(cell);
+        self.open(&cell.moved(m));
+        self.open(&cell.moved(m).moved(m));
+    }
+
+    /// Randomized depth-first search, connecting all cells once.
+    fn carve_spanning_tree(&mut self, rng: &mut ChaCha8Rng) {
+        let cells = self.left_cells();
+        let start = *cells.choose(rng).unwrap();
+        self.open(&start);
+        let mut stack = vec![start];
+        while let Some(&cell) = stack.last() {
+            let unvisited: Vec<(Move, Pos)> = self.left_neighbors(&cell).into_iter()
+                .filter(|(_, p)| !self.is_open(p)).collect();
+            if let Some(&(m, next)) = unvisited.choose(rng) {
+                self.carve(&cell, m);
+                stack.push(next);
+            } else {
+                stack.pop();
+            }
+        }
+    }
+
+    /// If the center column is made of walls (not cells), open a passage
+    /// across it, otherwise both halves are only connected through the center
+    /// column.
+    fn connect_halves(&mut self, rng: &mut ChaCha8Rng) {
+        let center_x = self.width as i16 / 2;
+        if center_x % 2 == 1 { return; }
+        let y = *(1..self.height as i16 - 1).step_by(2).collect::<Vec<_>>().choose(rng).unwrap();
+        self.carve(&Pos { x: center_x - 1, y }, Move::Right);
+    }
+
+    /// Open each wall between two cells with the given probability.
+    fn open_walls(&mut self, probability: f64, rng: &mut ChaCha8Rng) {
+        for cell in self.left_cells() {
+            for (m, _) in self.left_neighbors(&cell) {
+                // Only look right and down, to consider each wall once.
+                if matches!(m, Move::Right | Move::Down) && !self.is_open(&cell.moved(m))
+                    && rng.gen_bool(probability.clamp(0.0, 1.0)) {
+                    self.carve(&cell, m);
+                }
+            }
+        }
+    }
+
+    fn open_directions(&self, cell: &Pos) -> usize {
+        [Move::Up, Move::Down, Move::Left, Move::Right].into_iter()
+            .filter(|&m| self.is_open(&cell.moved(m)))
+            .count()
+    }
+
+    fn count_dead_ends(&self) -> usize {
+        self.empty_tiles().iter().filter(|p| self.open_directions(p) == 1).count()
+    }
+
+    /// Open walls from dead ends until at most 'max_dead_ends' remain.
+    fn remove_dead_ends(&mut self, max_dead_ends: usize, rng: &mut ChaCha8Rng) {
+        let mut dead_ends: Vec<Pos> = self.left_cells().into_iter()
+            .filter(|c| self.open_directions(c) == 1).collect();
+        dead_ends.shuffle(rng);
+        for cell in dead_ends {
+            if self.count_dead_ends() <= max_dead_ends { break; }
+            // Might no longer be a dead end after opening previous ones.
+            if self.open_directions(&cell) != 1 { continue; }
+            let closed: Vec<Move> = self.left_neighbors(&cell).into_iter()
+                .map(|(m, _)| m)
+                .filter(|&m| !self.is_open(&cell.moved(m)))
+                .collect();
+            if let Some(&m) = closed.choose(rng) {
+                self.carve(&cell, m);
+            }
+        }
+    }
+
+    fn empty_tiles(&self) -> Vec<Pos> {
+        (0..self.height as i16)
+            .flat_map(|y| (0..self.width as i16).map(move |x| Pos { x, y }))
+            .filter(|p| self.is_open(p))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::MAX_GRID_SIZE;
+    use crate::map_loader::to_text_layout;
+    use crate::pathfinding::PathfindingBackend;
+    use crate::simulation::{SimulationAction, State};
+    use std::collections::VecDeque;
+
+    /// Whether all empty tiles of a grid can be reached from 'start'.
+    fn is_connected(grid: &Grid, start: &Pos) -> bool {
+        let mut seen = vec![vec![false; grid.height as usize]; grid.width as usize];
+        let mut queue = VecDeque::from([*start]);
+        seen[start.x as usize][start.y as usize] = true;
+        let mut reached = 0;
+        while let Some(pos) = queue.pop_front() {
+            reached += 1;
+            for &m in grid.available_moves(&pos) {
+                let next = pos.moved(m);
+                if !seen[next.x as usize][next.y as usize] {
+                    seen[next.x as usize][next.y as usize] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached == grid.empty_tiles.len()
+    }
+
+    #[test]
+    fn test_generate_map() {
+        let options = GeneratorOptions { seed: 3, ..GeneratorOptions::default() };
+        let map = generate_map(&options).expect("map");
+        let game = &map.game;
+        let grid = &game.grid;
+        assert_eq!(grid.dims(), (21, 15));
+        assert!(is_connected(grid, &game.pos), "{}", to_text_layout(game));
+        // Mirrored left/right, walls around.
+        for x in 0..grid.width as usize {
+            for y in 0..grid.height as usize {
+                assert_eq!(grid.tiles[x][y], grid.tiles[grid.width as usize - 1 - x][y]);
+            }
+            assert!(grid.tiles[x][0] && grid.tiles[x][grid.height as usize - 1]);
+        }
+        let maze = Maze { width: 21, height: 15, tiles: grid.tiles.clone() };
+        assert!(maze.count_dead_ends() <= options.max_dead_ends,
+                "{}", to_text_layout(game));
+        let mut styles: Vec<Style> = game.threats.iter().map(|t| t.style).collect();
+        styles.sort_by_key(|s| s.name());
+        let mut expected = options.threats.clone();
+        expected.sort_by_key(|s| s.name());
+        assert_eq!(styles, expected);
+        assert!(game.threats.iter().all(|t| grid.is_empty(&t.pos) && t.pos != game.pos));
+        // Deterministic for a seed.
+        assert!(generate_map(&options).unwrap().game == map.game);
+        // Playable.
+        let mut state = State::new(map.game);
+        for _ in 0..20 {
+            state.simulate_tick(SimulationAction::Move { direction: None });
+        }
+    }
+
+    #[test]
+    fn test_generate_map_options() {
+        let perfect_maze = GeneratorOptions {
+            width: 11, height: 9, corridor_density: 0.0, max_dead_ends: usize::MAX,
+            threats: vec![Style::Bull], seed: 1,
+        };
+        let map = generate_map(&perfect_maze).expect("map");
+        assert!(is_connected(&map.game.grid, &map.game.pos));
+        assert_eq!(map.game.threats.len(), 1);
+        assert!(matches!(generate_map(&GeneratorOptions { width: 10, ..perfect_maze.clone() }),
+                         Err(GeneratorError::InvalidSize { .. })));
+        assert!(matches!(generate_map(&GeneratorOptions {
+            threats: vec![Style::Bull; 100], ..perfect_maze
+        }), Err(GeneratorError::TooManyThreats { .. })));
+    }
+
+    #[test]
+    fn test_generate_large_map() {
+        let options = GeneratorOptions { width: 301, height: 271, ..GeneratorOptions::default() };
+        let map = generate_map(&options).expect("map");
+        assert_eq!(map.game.grid.dims(), (301, 271));
+        assert!(is_connected(&map.game.grid, &map.game.pos));
+        let mut state = State::new(map.game);
+        assert_eq!(state.grid.backend(), PathfindingBackend::Lazy);
+        for _ in 0..20 {
+            state.simulate_tick(SimulationAction::Move { direction: None });
+        }
+        assert!(matches!(generate_map(&GeneratorOptions { width: MAX_GRID_SIZE + 2, ..options }),
+                         Err(GeneratorError::InvalidSize { .. })));
+    }
+
+    #[test]
+    fn test_mutate_map() {
+        let map = generate_map(&GeneratorOptions::default()).expect("map");
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut mutated = map.clone();
+        for _ in 0..50 {
+            mutated = mutate_map(&mutated, &mut rng);
+            let game = &mutated.game;
+            assert!(validate_game(game).is_empty(), "{}", to_text_layout(game));
+            assert_eq!(game.threats.len(), map.game.threats.len());
+            assert!(game.threats.iter().all(|t| game.grid.is_empty(&t.pos) && t.pos != game.pos));
+        }
+        assert!(mutated.game != map.game);
+    }
+}
@@ -1,15 +1,25 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
-use image::{ImageReader, Rgb};
+use image::{ImageReader, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
 
-use crate::grid::{Grid, Pos};
+use crate::grid::{debug_print, grid_dims, Grid, Pos, MAX_GRID_SIZE};
+use crate::pathfinding::{PathfindingGrid, COST_INFINITY};
 use crate::simulation::{Game, Style, Threat};
 
-const MAPS_FOLDER: &str = "maps";
+/// Where maps are, relative to the working directory, unless configured (see
+/// 'maps_dir').
+const DEFAULT_MAPS_FOLDER: &str = "maps";
+/// Environment variable to load maps from another folder.
+pub const MAPS_DIR_ENV_VAR: &str = "DEVNULL_MAPS_DIR";
 const LAYOUT_FILENAME: &str = "layout.png";
+/// Text version of the layout, used instead of 'layout.png' if present.
+const TEXT_LAYOUT_FILENAME: &str = "layout.txt";
+const META_FILENAME: &str = "meta.json";
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum TileType {
     NonWalkable,
     Walkable,
@@ -29,44 +39,234 @@ const TILE_LOOKUPS: &'static [(TileType, Rgb<u8>)] = &[
     (TileType::Threat(Style::Hawk), Rgb([125, 125, 0])),
 ];
 
+/// Characters of tiles in text layouts. Empty tiles can also be ' ' (e.g. to
+/// match 'make_grid').
+const TEXT_LOOKUPS: &[(TileType, char)] = &[
+    (TileType::NonWalkable, '#'),
+    (TileType::Walkable, '.'),
+    (TileType::Character, '@'),
+    (TileType::Threat(Style::Bull), 'B'),
+    (TileType::Threat(Style::Goldfish), 'G'),
+    (TileType::Threat(Style::Shark), 'S'),
+    (TileType::Threat(Style::Owl), 'O'),
+    (TileType::Threat(Style::Deer), 'D'),
+    (TileType::Threat(Style::Hawk), 'H'),
+];
+
+/// Color of a threat style in map layouts, also used when rendering games.
+pub(crate) fn threat_color(style: Style) -> Option<Rgb<u8>> {
+    TILE_LOOKUPS.iter().find_map(|(tile_type, color)| match tile_type {
+        TileType::Threat(s) if *s == style => Some(*color),
+        _ => None,
+    })
+}
+
 pub type Result<T> = std::result::Result<T, MapLoadingError>;
 
 #[derive(Debug)]
 pub enum MapLoadingError {
     NoMapsFolder(std::io::Error),
     FileLoadingError(std::io::Error),
+    FileWritingError(std::io::Error),
     LayoutDecodingError(image::error::ImageError),
+    LayoutEncodingError(image::error::ImageError),
+    /// Character of a text layout that isn't a known tile (both 0-indexed).
+    UnknownTile { line: usize, column: usize, tile: char },
+    /// Line of a text layout that doesn't match the width of the first one.
+    RaggedLayout { line: usize },
+    /// Layout files should end in '.png' or '.txt'.
+    UnknownLayoutFormat(PathBuf),
     NoSpawnPoint,
+    /// Another '@' of a text layout, after the first spawn point (both
+    /// 0-indexed).
+    MultipleSpawnPoints { line: usize, column: usize },
+    /// Layout is wider or taller than 'MAX_GRID_SIZE'.
+    TooLarge { width: usize, height: usize },
+    /// 'meta.json' of a map isn't valid.
+    MetaDecodingError { path: PathBuf, error: serde_json::Error },
 }
 
 #[derive(Clone)]
 pub struct Map {
     pub name: String,
     pub game: Game,
+    pub meta: MapMeta,
+}
+
+/// Metadata of a map, from its 'meta.json'.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MapMeta {
+    /// e.g. "challenge"
+    #[serde(rename = "type")]
+    pub map_type: String,
+    /// e.g. "easy"
+    pub difficulty: String,
+}
+
+impl Default for MapMeta {
+    /// For maps without a 'meta.json'.
+    fn default() -> Self {
+        Self { map_type: "unknown".to_string(), difficulty: "unknown".to_string() }
+    }
+}
+
+impl MapMeta {
+    /// Name of the category of the map, to group results, e.g. "challenge/easy".
+    pub fn category(&self) -> String {
+        format!("{}/{}", self.map_type, self.difficulty)
+    }
+}
+
+/// Maps included in the binary at build time, with the 'embedded-maps'
+/// feature. Listed along with the maps folder's, which take precedence.
+struct EmbeddedMap {
+    name: &'static str,
+    layout_png: Option<&'static [u8]>,
+    layout_txt: Option<&'static str>,
+    meta: Option<&'static str>,
+}
+
+const EMBEDDED_MAPS: &[EmbeddedMap] = include!(concat!(env!("OUT_DIR"), "/embedded_maps.rs"));
+
+/// Folder set with 'set_maps_dir', if any.
+static MAPS_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Load maps from 'dir' from now on. If unset, we use '$DEVNULL_MAPS_DIR', or
+/// 'maps' in the working directory.
+pub fn set_maps_dir(dir: Option<PathBuf>) {
+    *MAPS_DIR.write().unwrap() = dir;
+}
+
+/// Folder that maps are loaded from.
+pub fn maps_dir() -> PathBuf {
+    MAPS_DIR.read().unwrap().clone()
+        .or_else(|| std::env::var_os(MAPS_DIR_ENV_VAR).map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_MAPS_FOLDER))
 }
 
+/// Names of maps in the maps folder and 'EMBEDDED_MAPS'.
 pub fn list_map_names() -> Result<Vec<String>> {
-    let paths = fs::read_dir(MAPS_FOLDER)
-        .map_err(|e| MapLoadingError::NoMapsFolder(e))?;
-    let mut paths: Vec<String> = paths.filter_map(|entry| {
-        entry.ok().and_then(|e| {
-            e.path().file_name()
-                .and_then(|p| p.to_str().map(|s| String::from(s)))
-        })
-    }).collect();
-    paths.sort();
-    Ok(paths)
+    let mut names: Vec<String> = EMBEDDED_MAPS.iter().map(|map| map.name.to_string()).collect();
+    match fs::read_dir(maps_dir()) {
+        Ok(paths) => names.extend(paths.filter_map(|entry| {
+            entry.ok().and_then(|e| {
+                e.path().file_name()
+                    .and_then(|p| p.to_str().map(|s| String::from(s)))
+            })
+        })),
+        // Embedded maps are enough without a maps folder.
+        Err(_) if !EMBEDDED_MAPS.is_empty() => {},
+        Err(e) => return Err(MapLoadingError::NoMapsFolder(e)),
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
 }
 
+/// Load a map, from its 'layout.txt' if it has one, 'layout.png' otherwise.
 pub fn load_map(map_name: &str) -> Result<Map> {
-    let layout_path = Path::new(MAPS_FOLDER).join(map_name).join(LAYOUT_FILENAME);
-    let reader = ImageReader::open(layout_path)
+    let map_path = maps_dir().join(map_name);
+    if !map_path.is_dir() {
+        if let Some(embedded) = EMBEDDED_MAPS.iter().find(|map| map.name == map_name) {
+            return load_embedded_map(embedded);
+        }
+    }
+    let game = load_layout(&layout_path(map_name))?;
+    let meta = load_meta(&map_path.join(META_FILENAME))?;
+    Ok(Map { name: String::from(map_name), game, meta })
+}
+
+fn load_embedded_map(embedded: &EmbeddedMap) -> Result<Map> {
+    let game = match (embedded.layout_txt, embedded.layout_png) {
+        (Some(text), _) => parse_text_layout(text)?,
+        (None, Some(png)) => image_layout(&image::load_from_memory(png)
+            .map_err(MapLoadingError::LayoutDecodingError)?.to_rgb8())?,
+        (None, None) => return Err(MapLoadingError::FileLoadingError(std::io::Error::new(
+            std::io::ErrorKind::NotFound, format!("no layout embedded for {}", embedded.name)))),
+    };
+    let meta = match embedded.meta {
+        Some(text) => parse_meta(text, &Path::new(embedded.name).join(META_FILENAME))?,
+        None => MapMeta::default(),
+    };
+    Ok(Map { name: embedded.name.to_string(), game, meta })
+}
+
+/// Layout file 'load_map' uses for a map.
+pub fn layout_path(map_name: &str) -> PathBuf {
+    let map_path = maps_dir().join(map_name);
+    let text_layout_path = map_path.join(TEXT_LAYOUT_FILENAME);
+    if text_layout_path.exists() {
+        text_layout_path
+    } else {
+        map_path.join(LAYOUT_FILENAME)
+    }
+}
+
+/// Save a map in its own folder of 'maps_dir' (e.g. 'maps'), as 'layout.png'
+/// and 'meta.json', so it can be loaded like the server's.
+pub fn save_map(map: &Map, maps_dir: &Path) -> Result<()> {
+    let map_path = maps_dir.join(&map.name);
+    fs::create_dir_all(&map_path).map_err(MapLoadingError::FileWritingError)?;
+    save_layout(&map.game, &map_path.join(LAYOUT_FILENAME))?;
+    let meta = serde_json::to_string_pretty(&map.meta).expect("meta is serializable");
+    fs::write(map_path.join(META_FILENAME), meta).map_err(MapLoadingError::FileWritingError)
+}
+
+/// Load a map's 'meta.json', defaulting to unknown metadata if it has none.
+fn load_meta(path: &Path) -> Result<MapMeta> {
+    if !path.exists() {
+        return Ok(MapMeta::default());
+    }
+    let text = fs::read_to_string(path).map_err(MapLoadingError::FileLoadingError)?;
+    parse_meta(&text, path)
+}
+
+fn parse_meta(text: &str, path: &Path) -> Result<MapMeta> {
+    serde_json::from_str(text).map_err(|error| MapLoadingError::MetaDecodingError {
+        path: path.to_path_buf(), error,
+    })
+}
+
+/// Load a layout from an image ('.png') or text ('.txt') file.
+pub fn load_layout(path: &Path) -> Result<Game> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => load_image_layout(path),
+        Some("txt") => {
+            let text = fs::read_to_string(path)
+                .map_err(MapLoadingError::FileLoadingError)?;
+            parse_text_layout(&text)
+        },
+        _ => Err(MapLoadingError::UnknownLayoutFormat(path.to_path_buf())),
+    }
+}
+
+/// Save a layout to an image ('.png') or text ('.txt') file, e.g. to convert
+/// between both formats.
+pub fn save_layout(game: &Game, path: &Path) -> Result<()> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => to_image_layout(game).save(path)
+            .map_err(MapLoadingError::LayoutEncodingError),
+        Some("txt") => fs::write(path, to_text_layout(game))
+            .map_err(MapLoadingError::FileWritingError),
+        _ => Err(MapLoadingError::UnknownLayoutFormat(path.to_path_buf())),
+    }
+}
+
+fn read_image(path: &Path) -> Result<RgbImage> {
+    let reader = ImageReader::open(path)
         .map_err(|e| MapLoadingError::FileLoadingError(e))?;
     let layout = reader.decode()
         .map_err(|e| MapLoadingError::LayoutDecodingError(e))?;
-    let width = layout.width();
-    let height = layout.height();
-    let rgb = layout.to_rgb8();
+    Ok(layout.to_rgb8())
+}
+
+fn load_image_layout(path: &Path) -> Result<Game> {
+    image_layout(&read_image(path)?)
+}
+
+fn image_layout(rgb: &RgbImage) -> Result<Game> {
+    let (width, height) = rgb.dimensions();
+    let dims = layout_dims(width as usize, height as usize)?;
     // Following
     // https://github.com/JesseEmond/blitz-2025-registration/blob/971949e077a937a51844f98a9a02f2855c80cdc4/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/maps/map_loader.decomp.js#L243
     // default to walkable
@@ -91,24 +291,348 @@ pub fn load_map(map_name: &str) -> Result<Map> {
     if pos.is_none() {
         return Err(MapLoadingError::NoSpawnPoint);
     }
-    Ok(Map {
-        name: String::from(map_name),
-        game: Game {
-            tick: 1,
-            alive: true,
-            grid: Grid::new(width as u8, height as u8, tiles),
-            pos: pos.unwrap(),
-            threats,
-        },
+    Ok(Game {
+        tick: 1,
+        alive: true,
+        grid: Grid::new(dims.0, dims.1, tiles),
+        pos: pos.unwrap(),
+        threats,
     })
 }
 
+fn layout_dims(width: usize, height: usize) -> Result<(u16, u16)> {
+    grid_dims(width, height).ok_or(MapLoadingError::TooLarge { width, height })
+}
+
+/// Parse a text layout, one line per row: '#' for walls, '.' (or ' ') for
+/// empty tiles, '@' for the spawn point and a letter per threat style (see
+/// 'TEXT_LOOKUPS'). Threats and the spawn point are on empty tiles. Like with
+/// images, threats are ordered by rows, then columns.
+pub fn parse_text_layout(text: &str) -> Result<Game> {
+    let lines: Vec<Vec<char>> = text.lines().map(|line| line.chars().collect()).collect();
+    let width = lines.first().map_or(0, |line| line.len());
+    let height = lines.len();
+    let dims = layout_dims(width, height)?;
+    let mut tiles = vec![vec![false; height]; width];
+    let mut pos = None;
+    let mut threats = vec![];
+    for (y, line) in lines.iter().enumerate() {
+        if line.len() != width {
+            return Err(MapLoadingError::RaggedLayout { line: y });
+        }
+        for (x, &c) in line.iter().enumerate() {
+            let p = Pos { x: x as i16, y: y as i16 };
+            let tile_type = if c == ' ' {
+                TileType::Walkable
+            } else {
+                TEXT_LOOKUPS.iter().find(|(_, tile)| *tile == c)
+                    .map(|(tile_type, _)| tile_type.clone())
+                    .ok_or(MapLoadingError::UnknownTile { line: y, column: x, tile: c })?
+            };
+            match tile_type {
+                TileType::Character if pos.is_some() =>
+                    return Err(MapLoadingError::MultipleSpawnPoints { line: y, column: x }),
+                TileType::Character => pos = Some(p),
+                TileType::Walkable => {},
+                TileType::NonWalkable => tiles[x][y] = true,
+                TileType::Threat(style) => threats.push(Threat::spawn(p, style)),
+            }
+        }
+    }
+    Ok(Game {
+        tick: 1,
+        alive: true,
+        grid: Grid::new(dims.0, dims.1, tiles),
+        pos: pos.ok_or(MapLoadingError::NoSpawnPoint)?,
+        threats,
+    })
+}
+
+/// Text layout of a game's starting state (see 'parse_text_layout').
+/// Threats of unknown style are shown as '?', which can't be parsed back.
+pub fn to_text_layout(game: &Game) -> String {
+    let grid = &game.grid;
+    let mut text = String::new();
+    for y in 0..grid.height as i16 {
+        for x in 0..grid.width as i16 {
+            let pos = Pos { x, y };
+            text.push(text_tile(layout_tile_type(game, &pos)));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Image layout of a game's starting state, as 'layout.png' files. Threats of
+/// unknown style are magenta, which isn't a known tile.
+pub fn to_image_layout(game: &Game) -> RgbImage {
+    let grid = &game.grid;
+    RgbImage::from_fn(grid.width as u32, grid.height as u32, |x, y| {
+        let pos = Pos { x: x as i16, y: y as i16 };
+        match layout_tile_type(game, &pos) {
+            TileType::Threat(style) => threat_color(style).unwrap_or(Rgb([255, 0, 255])),
+            tile_type => TILE_LOOKUPS.iter().find(|(t, _)| *t == tile_type)
+                .map(|(_, color)| *color).unwrap(),
+        }
+    })
+}
+
+/// Tile type to write in layouts at 'pos'. If multiple things are on the same
+/// tile, the player is shown over threats.
+fn layout_tile_type(game: &Game, pos: &Pos) -> TileType {
+    if game.pos == *pos {
+        TileType::Character
+    } else if let Some(threat) = game.threats.iter().find(|t| t.pos == *pos) {
+        TileType::Threat(threat.style)
+    } else if game.grid.is_empty(pos) {
+        TileType::Walkable
+    } else {
+        TileType::NonWalkable
+    }
+}
+
+fn text_tile(tile_type: TileType) -> char {
+    TEXT_LOOKUPS.iter().find(|(t, _)| *t == tile_type).map_or('?', |(_, c)| *c)
+}
+
 pub fn load_all_maps() -> Result<Vec<Map>> {
     list_map_names()?.iter()
         .map(|name| load_map(name))
         .collect()
 }
 
+/// Find the known map that 'game' is played on. Threats of a style that we
+/// don't know are kept as 'Style::Unknown' in the returned map, we'll identify
+/// them from their moves.
+pub fn find_matching_map(game: &Game) -> Result<Option<Map>> {
+    let map = load_all_maps()?.into_iter().find(|m| {
+        let is_same = check_is_same_map(m, game);
+        if !is_same { println!("Not that one!"); }
+        is_same
+    });
+    Ok(map.map(|mut map| {
+        for (map_t, game_t) in map.game.threats.iter_mut().zip(game.threats.iter()) {
+            if game_t.style == Style::Unknown {
+                map_t.style = Style::Unknown;
+            }
+        }
+        map
+    }))
+}
+
+/// 'type' of maps made from games on maps we didn't know, in their 'MapMeta'.
+pub const OBSERVED_MAP_TYPE: &str = "observed";
+
+/// Map that 'game' is played on: the known one if any, otherwise a new map made
+/// from 'game' (see 'map_from_game'), saved to the maps folder if 'save_new_map'
+/// is set, to evaluate it later. Maps with threats of unknown style aren't
+/// saved: layouts can't represent them, so the saved map would never match.
+pub fn find_or_create_map(game: &Game, save_new_map: bool) -> Result<Map> {
+    if let Some(map) = find_matching_map(game)? {
+        return Ok(map);
+    }
+    let map = map_from_game(game);
+    println!("New map! Playing it as '{}'.", map.name);
+    if save_new_map && game.threats.iter().any(|t| t.style == Style::Unknown) {
+        println!("[WARNING] Not saving the new map, it has threats of unknown style.");
+    } else if save_new_map {
+        let maps_dir = maps_dir();
+        save_map(&map, &maps_dir)?;
+        println!("Saved new map to {}", maps_dir.join(&map.name).display());
+    }
+    Ok(map)
+}
+
+/// Map made from the first observed state of a game on a map we don't know.
+/// Threats are spawned where they are (with their RNG as the server's on
+/// spawn), facing where we see them. It is named after a hash of its layout.
+pub fn map_from_game(game: &Game) -> Map {
+    let threats = game.threats.iter().map(|observed| Threat {
+        dir: observed.dir,
+        personality: observed.personality,
+        ..Threat::spawn(observed.pos, observed.style)
+    }).collect();
+    Map {
+        name: format!("observed_{:016x}", fnv1a_hash(to_text_layout(game).as_bytes())),
+        game: Game { threats, ..game.clone() },
+        meta: MapMeta { map_type: OBSERVED_MAP_TYPE.to_string(), ..MapMeta::default() },
+    }
+}
+
+/// 64-bit FNV-1a hash. Unlike 'DefaultHasher', it is the same across Rust
+/// releases, so observed maps keep their names.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+/// Verbose check whether an observed game state is a given known map.
+fn check_is_same_map(map: &Map, game: &Game) -> bool {
+    // The following is needlessly thorough/verbose, but helps find bugs in map
+    // loading logic.
+    println!("Is it map {}?", map.name);
+    // Catch potential init issues
+    assert_eq!(map.game.tick, game.tick);
+    assert_eq!(map.game.alive, game.alive);
+
+    println!("Pos: {:?} =? {:?}", map.game.pos, game.pos);
+    if map.game.pos != game.pos { return false; }
+
+    println!("Grid dims: {:?} =? {:?}", map.game.grid.dims(), game.grid.dims());
+    if map.game.grid.dims() != game.grid.dims() { return false; }
+
+    println!("# threats: {} =? {}", map.game.threats.len(), game.threats.len());
+    if map.game.threats.len() != game.threats.len() { return false; }
+
+    // Check exact order of threats, too, just to ensure map loading is the
+    // exact same as the server.
+    for (i, (map_t, game_t)) in map.game.threats.iter().zip(game.threats.iter()).enumerate() {
+        println!("- Threat #{}:", i + 1);
+        println!("  - style: {:?} =? {:?}", map_t.style, game_t.style);
+        println!("  -   pos: {:?} =? {:?}", map_t.pos, game_t.pos);
+        println!("  -   dir: {:?} =? {:?}", map_t.dir, game_t.dir);
+        println!("  - personality: {:?} =? {:?}", map_t.personality, game_t.personality);
+        // A style we do not know could be any of the map's.
+        let mut map_t = map_t.clone();
+        if game_t.style == Style::Unknown { map_t.style = Style::Unknown; }
+        if map_t != *game_t { return false; }
+    }
+
+    println!("layout =?");
+    if map.game.grid.tiles != game.grid.tiles {
+        println!("Map:");
+        debug_print(&map.game.grid, vec![]);
+        println!("Game:");
+        debug_print(&game.grid, vec![]);
+        return false;
+    }
+
+    true
+}
+
+/// Problem found in a map layout by 'validate_layout'.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapIssue {
+    /// Pixel that doesn't match any known tile color, ignored when loading.
+    UnmatchedPixel { pos: Pos, color: Rgb<u8> },
+    /// Images use the last one (in reading order), text layouts fail to load.
+    MultipleSpawnPoints(Vec<Pos>),
+    NoSpawnPoint,
+    /// Layout can't be loaded (e.g. unknown text tile).
+    LoadingError(String),
+    /// Wider or taller than 'MAX_GRID_SIZE'.
+    TooLarge { width: usize, height: usize },
+    SpawnOnWall(Pos),
+    ThreatOnWall { threat: usize, pos: Pos },
+    /// Walkable tiles that can't be reached from the spawn point.
+    UnreachableRegion { size: usize, example: Pos },
+    /// Hawks pick among these, they would panic.
+    NoBestIntersections,
+}
+
+impl std::fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnmatchedPixel { pos, color } =>
+                write!(f, "pixel at {:?} has unknown color {:?}, ignored", pos, color.0),
+            Self::MultipleSpawnPoints(positions) =>
+                write!(f, "{} spawn points ({:?}), images use the last one",
+                       positions.len(), positions),
+            Self::NoSpawnPoint => write!(f, "no spawn point"),
+            Self::LoadingError(error) => write!(f, "can't load layout: {}", error),
+            Self::TooLarge { width, height } =>
+                write!(f, "{}x{} is too large, max {}x{}", width, height, MAX_GRID_SIZE,
+                       MAX_GRID_SIZE),
+            Self::SpawnOnWall(pos) => write!(f, "spawn point {:?} is on a wall", pos),
+            Self::ThreatOnWall { threat, pos } =>
+                write!(f, "threat #{} at {:?} is on a wall", threat, pos),
+            Self::UnreachableRegion { size, example } =>
+                write!(f, "{} walkable tiles unreachable from spawn (e.g. {:?})", size, example),
+            Self::NoBestIntersections =>
+                write!(f, "no intersections for Hawks to idle at, they would panic"),
+        }
+    }
+}
+
+/// Check a layout file for problems that loading would ignore or that would
+/// break our simulation. Errors are for files that can't be read at all.
+pub fn validate_layout(path: &Path) -> Result<Vec<MapIssue>> {
+    let mut issues = Vec::new();
+    let (width, height, spawns) = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => {
+            let rgb = read_image(path)?;
+            let mut spawns = Vec::new();
+            for (x, y, pixel) in rgb.enumerate_pixels() {
+                let pos = Pos { x: x as i16, y: y as i16 };
+                match get_best_match(*pixel) {
+                    Some(TileType::Character) => spawns.push(pos),
+                    Some(_) => {},
+                    None => issues.push(MapIssue::UnmatchedPixel { pos, color: *pixel }),
+                }
+            }
+            (rgb.width() as usize, rgb.height() as usize, spawns)
+        },
+        Some("txt") => {
+            let text = fs::read_to_string(path).map_err(MapLoadingError::FileLoadingError)?;
+            let spawns = text.lines().enumerate().flat_map(|(y, line)| {
+                line.chars().enumerate().filter(|&(_, c)| c == '@')
+                    .map(move |(x, _)| Pos { x: x as i16, y: y as i16 })
+            }).collect();
+            let width = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+            (width, text.lines().count(), spawns)
+        },
+        _ => return Err(MapLoadingError::UnknownLayoutFormat(path.to_path_buf())),
+    };
+    match spawns.len() {
+        0 => issues.push(MapIssue::NoSpawnPoint),
+        1 => {},
+        _ => issues.push(MapIssue::MultipleSpawnPoints(spawns)),
+    }
+    if grid_dims(width, height).is_none() {
+        issues.push(MapIssue::TooLarge { width, height });
+        return Ok(issues);
+    }
+    match load_layout(path) {
+        Ok(game) => issues.extend(validate_game(&game)),
+        // Already reported above.
+        Err(MapLoadingError::NoSpawnPoint | MapLoadingError::MultipleSpawnPoints { .. }) => {},
+        Err(e) => issues.push(MapIssue::LoadingError(format!("{:?}", e))),
+    }
+    Ok(issues)
+}
+
+/// Check a loaded game for problems that would break our simulation.
+pub fn validate_game(game: &Game) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+    let grid = &game.grid;
+    if !grid.is_empty(&game.pos) {
+        issues.push(MapIssue::SpawnOnWall(game.pos));
+    }
+    for (i, threat) in game.threats.iter().enumerate() {
+        if !grid.is_empty(&threat.pos) {
+            issues.push(MapIssue::ThreatOnWall { threat: i, pos: threat.pos });
+        }
+    }
+    if grid.best_intersections.is_empty() {
+        issues.push(MapIssue::NoBestIntersections);
+    }
+    if grid.is_empty(&game.pos) {
+        let pathfinding = PathfindingGrid::new(grid.clone());
+        let mut unreachable: Vec<Pos> = grid.empty_tiles.iter()
+            .filter(|p| pathfinding.get_cost(&game.pos, p) == COST_INFINITY)
+            .cloned().collect();
+        // Group unreachable tiles by region, to report each separately.
+        while let Some(&example) = unreachable.first() {
+            let (region, rest): (Vec<Pos>, Vec<Pos>) = unreachable.into_iter()
+                .partition(|p| pathfinding.get_cost(&example, p) != COST_INFINITY);
+            issues.push(MapIssue::UnreachableRegion { size: region.len(), example });
+            unreachable = rest;
+        }
+    }
+    issues
+}
+
 fn get_best_match(pixel: Rgb<u8>) -> Option<TileType> {
     // Reference:
     // https://github.com/JesseEmond/blitz-2025-registration/blob/971949e077a937a51844f98a9a02f2855c80cdc4/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/maps/map_loader.decomp.js#L182-L202
@@ -129,6 +653,7 @@ fn color_distance(a: Rgb<u8>, b: Rgb<u8>) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::map_generator::{generate_map, GeneratorOptions};
 
     #[test]
     fn test_list_map_names_returns_some() {
@@ -149,6 +674,110 @@ mod tests {
         assert!(map.game.grid.is_empty(&Pos { x: 1, y: 1 }));
         assert_eq!(map.game.pos, Pos { x: 9, y: 7 });
         assert_eq!(map.game.threats.len(), 6);
+        assert_eq!(map.meta, MapMeta {
+            map_type: "challenge".to_string(), difficulty: "easy".to_string(),
+        });
+        assert_eq!(map.meta.category(), "challenge/easy");
+    }
+
+    #[test]
+    fn test_text_layout_round_trip() {
+        let game = load_map("challenge1").expect("map").game;
+        let text = to_text_layout(&game);
+        assert!(text.starts_with("######################\n#B.................."));
+        let parsed = parse_text_layout(&text).expect("text layout");
+        assert!(parsed == game, "layouts differ:\n{}", to_text_layout(&parsed));
+        let image = to_image_layout(&game);
+        assert_eq!(image.dimensions(), (22, 15));
+        assert_eq!(*image.get_pixel(9, 7), Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn test_parse_text_layout() {
+        let game = parse_text_layout(concat!(
+            "#####\n",
+            "#@ O#\n",
+            "#B..#\n",
+            "#####\n",
+        )).expect("text layout");
+        assert_eq!(game.grid.dims(), (5, 4));
+        assert_eq!(game.pos, Pos { x: 1, y: 1 });
+        let threats: Vec<_> = game.threats.iter().map(|t| (t.pos, t.style)).collect();
+        assert_eq!(threats, vec![(Pos { x: 3, y: 1 }, Style::Owl),
+                                 (Pos { x: 1, y: 2 }, Style::Bull)]);
+        assert!(game.grid.is_empty(&Pos { x: 2, y: 1 }));
+        assert!(!game.grid.is_empty(&Pos { x: 0, y: 0 }));
+        assert!(matches!(parse_text_layout("###\n#@x\n"),
+                         Err(MapLoadingError::UnknownTile { line: 1, column: 2, tile: 'x' })));
+        assert!(matches!(parse_text_layout("###\n#@\n"),
+                         Err(MapLoadingError::RaggedLayout { line: 1 })));
+        assert!(matches!(parse_text_layout("###\n#.#\n"),
+                         Err(MapLoadingError::NoSpawnPoint)));
+        assert!(matches!(parse_text_layout("####\n#@.#\n#.@#\n"),
+                         Err(MapLoadingError::MultipleSpawnPoints { line: 2, column: 2 })));
+    }
+
+    #[test]
+    fn test_large_layouts() {
+        let game = generate_map(&GeneratorOptions { width: 301, height: 271, ..Default::default() })
+            .expect("map").game;
+        let parsed = parse_text_layout(&to_text_layout(&game)).expect("text layout");
+        assert!(parsed == game);
+        let parsed = image_layout(&to_image_layout(&game)).expect("image layout");
+        assert_eq!(parsed.grid.dims(), (301, 271));
+        assert!(parsed.grid == game.grid);
+        let too_wide = format!("{}@\n", "#".repeat(MAX_GRID_SIZE));
+        assert!(matches!(parse_text_layout(&too_wide),
+                         Err(MapLoadingError::TooLarge { width, height: 1 })
+                         if width == MAX_GRID_SIZE + 1));
+    }
+
+    #[test]
+    fn test_validate_layout() {
+        assert_eq!(validate_layout(&layout_path("challenge1")).expect("validate"), vec![]);
+        let path = std::env::temp_dir().join(
+            format!("devnull_validate_test_{}.txt", std::process::id()));
+        fs::write(&path, concat!(
+            "#######\n",
+            "#@.#.O#\n",
+            "#.@####\n",
+            "#######\n",
+        )).unwrap();
+        let issues = validate_layout(&path).expect("validate");
+        assert_eq!(issues, vec![
+            MapIssue::MultipleSpawnPoints(vec![Pos { x: 1, y: 1 }, Pos { x: 2, y: 2 }]),
+        ]);
+        fs::write(&path, concat!(
+            "#######\n",
+            "#@.#.O#\n",
+            "#..####\n",
+            "#######\n",
+        )).unwrap();
+        let issues = validate_layout(&path).expect("validate");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(issues, vec![
+            MapIssue::NoBestIntersections,
+            MapIssue::UnreachableRegion { size: 2, example: Pos { x: 4, y: 1 } },
+        ]);
+    }
+
+    #[test]
+    fn test_map_from_game() {
+        let known = load_map("challenge1").expect("map");
+        // Same threats as loading the map.
+        let map = map_from_game(&known.game);
+        assert!(map.game == known.game);
+        assert!(map.name.starts_with("observed_"));
+        // Names are stable across builds (FNV-1a test vector).
+        assert_eq!(fnv1a_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(map.meta.map_type, OBSERVED_MAP_TYPE);
+        // Falls back to it for maps we don't know.
+        let mut game = known.game.clone();
+        game.pos = Pos { x: 1, y: 2 };
+        let map = find_or_create_map(&game, /*save_new_map=*/false).expect("map");
+        assert_eq!(map.name, map_from_game(&game).name);
+        assert_eq!(map.game.pos, game.pos);
+        assert_eq!(find_or_create_map(&known.game, false).expect("map").name, "challenge1");
     }
 
     #[test]
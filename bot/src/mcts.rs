@@ -19,7 +19,7 @@
 ///   let next_move = algorithm.search().next_action;
 // TODO: split to its own module, put interfaces vs. implementations in diff files
 
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use rand::seq::{IteratorRandom, SliceRandom};
 use rand_chacha::ChaCha8Rng;
 use std::time::{Duration, Instant};
@@ -34,17 +34,17 @@ pub trait MCTS: Sized {
     // Game related
     /// Possible action that can be done at a particular state.
     type Action: Clone + PartialEq + Send + Sync;
-    /// State of the game.
-    type State: Clone + SearchState<Self>;
+    /// State of the game. Shared between threads by parallel searches.
+    type State: Clone + Send + Sync + SearchState<Self>;
     /// Possible actions at a given state.
     /// Configurable to allow optimized states (e.g. smallvec)
     type ActionSpace: std::ops::Deref<Target = [Self::Action]> + IntoIterator<Item = Self::Action>;
 
     // Search related
     /// Evaluator of a state's goodness.
-    type Evaluator: Evaluator<Self>;
+    type Evaluator: Evaluator<Self> + Clone;
     /// Budget constraining the search.
-    type Budget: SearchBudget;
+    type Budget: SearchBudget + Clone;
 }
 
 /// State within the game.
@@ -58,6 +58,11 @@ pub trait SearchState<Spec: MCTS> {
     fn is_terminal(&self) -> bool;
     /// If this is a winning state where we can stop the search altogether.
     fn is_win(&self) -> bool;
+    /// For stochastic states (e.g. opponents that we can only model with a
+    /// distribution), sample the random parts of the state from 'seed', so
+    /// that applying actions is deterministic from there.
+    /// Deterministic states can keep the default no-op.
+    fn determinize(&mut self, _seed: u64) {}
 }
 
 pub type Score = f32;
@@ -79,57 +84,174 @@ pub trait SimulationPolicy<Spec: MCTS> {
 
 /// Search budget, called onced per evaluation function evaluation to decide if
 /// the search should proceed.
-pub trait SearchBudget {
+pub trait SearchBudget : Send + Sync {
     fn is_over_budget(&self, stats: &Stats) -> bool;
 }
 
 // Algorithm that drives the search. Made up of composable components.
 pub struct Algorithm<'a, Spec: MCTS> {
     pub state: Spec::State,
-    component: Box<dyn SearchComponent<Spec> + 'a + Send + Sync>,
-    params: SearchParams<Spec>,
+    /// Searches run on each call to 'search', on separate threads if there is
+    /// more than one (see 'root_parallel_algorithm'). The first one is ours.
+    workers: Vec<Worker<'a, Spec>>,
 }
 impl<'a, Spec: MCTS> Algorithm<'a, Spec> {
     pub fn new(component: Box<dyn SearchComponent<Spec> + 'a + Send + Sync>,
                params: SearchParams<Spec>, state: Spec::State) -> Self {
-        Self { component, params, state }
+        Self { workers: vec![Worker { component, params }], state }
     }
 
     /// Search from a given state, apply the best move found.
     pub fn search(&mut self) -> Results<Spec> {
-        let mut outcome = Outcome::new();
-        self.params.stats = Stats::new();
-        while !self.params.search_is_done() && !self.state.is_terminal() && !outcome.win {
-            let decided = Vec::new();
-            self.component.reset_prefix(&decided);
-            outcome.update_best(
-                self.component.execute(&mut self.params, &self.state, decided));
-        }
+        let state = &self.state;
+        let (ours, others) = self.workers.split_first_mut().expect("no workers");
+        let outcomes: Vec<Outcome> = std::thread::scope(|scope| {
+            let handles: Vec<_> = others.iter_mut()
+                .map(|worker| scope.spawn(move || worker.run(state)))
+                .collect();
+            let mut outcomes = vec![ours.run(state)];
+            outcomes.extend(handles.into_iter().map(|handle| handle.join().expect("worker")));
+            outcomes
+        });
+        // Report root statistics before committing, which forgets them.
+        let worker_action_stats: Vec<_> = self.workers.iter_mut().map(|worker| {
+            worker.component.reset_prefix(&Vec::new());
+            worker.component.action_stats()
+        }).collect();
+        let (outcome, action_stats) = if self.workers.len() == 1 {
+            (outcomes.into_iter().next().unwrap(),
+             worker_action_stats.into_iter().next().unwrap())
+        } else {
+            self.merge_workers(outcomes, worker_action_stats)
+        };
         let mut score = outcome.score;
         // Evaluate current state's score if we did not have time to search.
         if outcome.is_empty() {
-            score = self.params.evaluate(&self.state);
+            score = self.workers[0].params.evaluate(&self.state);
         }
+        let best_sequence = self.action_sequence(&outcome.actions);
         let next_action_idx = outcome.actions.iter().next();
         let next_action = next_action_idx
             .map(|&idx| self.state.generate_actions()[idx].clone());
         if let Some(ref action) = next_action {
-            self.component.commit(*next_action_idx.unwrap());
+            for worker in &mut self.workers {
+                worker.component.commit(*next_action_idx.unwrap());
+            }
             self.state.apply_action(action.clone());
         }
+        let mut stats = self.workers[0].params.stats.clone();
+        for worker in &self.workers[1..] {
+            stats.num_evals += worker.params.stats.num_evals;
+            stats.highest_score_seen =
+                stats.highest_score_seen.max(worker.params.stats.highest_score_seen);
+        }
         Results {
-            stats: self.params.stats.clone(),
+            stats,
             score,
             next_action,
-            is_win: outcome.win
+            is_win: outcome.win,
+            best_sequence,
+            action_stats,
         }
     }
+
+    /// Combine the searches of parallel workers: sum their root statistics
+    /// and pick the most visited action (a vote weighted by visits). Without
+    /// statistics (e.g. no 'Select'), or if a worker won, pick the best
+    /// outcome instead.
+    fn merge_workers(&self, outcomes: Vec<Outcome>,
+                     worker_action_stats: Vec<Vec<ActionStats<Spec>>>)
+        -> (Outcome, Vec<ActionStats<Spec>>) {
+        let mut merged: Vec<ActionStats<Spec>> = Vec::new();
+        // Unvisited actions have nothing to average.
+        let visited = worker_action_stats.into_iter().flatten().filter(|stats| stats.visits > 0);
+        for stats in visited {
+            match merged.iter_mut().find(|m| m.action == stats.action) {
+                Some(m) => {
+                    let visits = m.visits + stats.visits;
+                    m.avg_score = (m.avg_score * m.visits as Score
+                                   + stats.avg_score * stats.visits as Score) / visits as Score;
+                    m.visits = visits;
+                },
+                None => merged.push(stats),
+            }
+        }
+        let mut best = Outcome::new();
+        for outcome in &outcomes {
+            best.update_best(outcome.clone());
+        }
+        let voted = merged.iter()
+            .max_by_key(|m| (m.visits, OrderedFloat(m.avg_score)));
+        let Some(voted) = voted.filter(|_| !best.win) else {
+            return (best, merged);
+        };
+        let action_idx = self.state.generate_actions().iter()
+            .position(|action| *action == voted.action).expect("voted action");
+        // Best sequence seen that starts with the voted action.
+        let mut outcome = Outcome::new();
+        for other in outcomes.into_iter().filter(|o| o.actions.first() == Some(&action_idx)) {
+            outcome.update_best(other);
+        }
+        if outcome.is_empty() {
+            outcome = Outcome { actions: vec![action_idx], score: voted.avg_score, win: false };
+        }
+        (outcome, merged)
+    }
+
+    /// Actions matching a sequence of action indices, from the current state.
+    fn action_sequence(&self, action_indices: &[usize]) -> Vec<Spec::Action> {
+        let mut state = self.state.clone();
+        let mut actions = Vec::with_capacity(action_indices.len());
+        for &idx in action_indices {
+            if state.is_terminal() { break; }
+            let action = state.generate_actions()[idx].clone();
+            state.apply_action(action.clone());
+            actions.push(action);
+        }
+        actions
+    }
+}
+/// Independent search over the state of an 'Algorithm'.
+struct Worker<'a, Spec: MCTS> {
+    component: Box<dyn SearchComponent<Spec> + 'a + Send + Sync>,
+    params: SearchParams<Spec>,
 }
+impl<Spec: MCTS> Worker<'_, Spec> {
+    /// Search from 'state' until over budget, return the best outcome seen.
+    fn run(&mut self, state: &Spec::State) -> Outcome {
+        let mut outcome = Outcome::new();
+        self.params.stats = Stats::new();
+        while !self.params.search_is_done() && !state.is_terminal() && !outcome.win {
+            let decided = Vec::new();
+            self.component.reset_prefix(&decided);
+            // Sample stochastic parts for components that don't iterate
+            // themselves, others resample on each iteration (e.g. 'Repeat').
+            let mut determinized = state.clone();
+            self.params.determinize(&mut determinized);
+            outcome.update_best(
+                self.component.execute(&mut self.params, &determinized, decided));
+        }
+        outcome
+    }
+}
+
 pub struct Results<Spec: MCTS> {
     pub stats: Stats,
     pub score: Score,
     pub is_win: bool,
     pub next_action: Option<Spec::Action>,
+    /// Best sequence of actions found, starting with 'next_action'.
+    pub best_sequence: Vec<Spec::Action>,
+    /// Statistics of the actions considered from the searched state, if the
+    /// search keeps any (e.g. 'Select').
+    pub action_stats: Vec<ActionStats<Spec>>,
+}
+/// Statistics about an action considered from the searched state.
+pub struct ActionStats<Spec: MCTS> {
+    pub action: Spec::Action,
+    pub visits: usize,
+    /// Average score of the outcomes seen through this action.
+    pub avg_score: Score,
 }
 #[derive(Clone)]
 pub struct Stats {
@@ -190,9 +312,18 @@ pub trait SearchComponent<Spec: MCTS> {
     fn reset_prefix(&mut self, decided: &Vec<usize>);
     /// Apply the next best action index, propagate to subcomponents.
     /// Opportunity to forget internal state related to other actions.
-    /// We pass the action index picked to double-check that it is the expected
-    /// one. 
+    /// With parallel workers, this might not be the action this component
+    /// found best (see 'share_commits').
     fn commit(&mut self, action_idx: usize);
+    /// Called when running as one of several parallel workers, where
+    /// committed actions can come from other workers (see
+    /// 'root_parallel_algorithm'). Propagate to subcomponents.
+    fn share_commits(&mut self) {}
+    /// Statistics of actions from the current prefix, if the component (or
+    /// subcomponents) keeps any.
+    fn action_stats(&self) -> Vec<ActionStats<Spec>> {
+        Vec::new()
+    }
 }
 
 /// Follow a simulation policy until a terminal state or max configured rollout
@@ -229,6 +360,9 @@ impl<'a, Spec: MCTS> SearchComponent<Spec> for Simulate<'a, Spec> {
     fn commit(&mut self, action_idx: usize) {
         self.yielder.advance(action_idx);
     }
+    fn share_commits(&mut self) {
+        self.yielder.shared = true;
+    }
 }
 
 /// Repeat a subcomponent a fixed amount of times, return the last iteration's
@@ -250,7 +384,7 @@ impl<Spec: MCTS> SearchComponent<Spec> for Repeat<'_, Spec> {
         for _ in 0..self.times {
             if params.search_is_done() || best_outcome.win { break; }
             best_outcome.update_best(
-                self.invoker.invoke(params, state, decided.clone()));
+                self.invoker.invoke_determinized(params, state, decided.clone()));
         }
         best_outcome
     }
@@ -260,6 +394,12 @@ impl<Spec: MCTS> SearchComponent<Spec> for Repeat<'_, Spec> {
     fn commit(&mut self, action_idx: usize) {
         self.invoker.commit(action_idx);
     }
+    fn share_commits(&mut self) {
+        self.invoker.share_commits();
+    }
+    fn action_stats(&self) -> Vec<ActionStats<Spec>> {
+        self.invoker.subcomponent.action_stats()
+    }
 }
 
 /// For the remainder of the steps, run a sub-search to pick the next action.
@@ -280,7 +420,7 @@ impl<Spec: MCTS> SearchComponent<Spec> for Step<'_, Spec> {
         while !params.state_is_done(&state, decided.len()) && !params.search_is_done()
             && !best_outcome.win {
             best_outcome.update_best(
-                self.invoker.invoke(params, &state, decided.clone()));
+                self.invoker.invoke_determinized(params, &state, decided.clone()));
             if best_outcome.actions.is_empty() {
                 // Subsearch exited without a solution (e.g. over search budget)
                 assert!(params.search_is_done());
@@ -303,6 +443,12 @@ impl<Spec: MCTS> SearchComponent<Spec> for Step<'_, Spec> {
     fn commit(&mut self, action_idx: usize) {
         self.invoker.commit(action_idx);
     }
+    fn share_commits(&mut self) {
+        self.invoker.share_commits();
+    }
+    fn action_stats(&self) -> Vec<ActionStats<Spec>> {
+        self.invoker.subcomponent.action_stats()
+    }
 }
 
 /// Evaluate each possible next move with a sub-search algorithm. Pick the best.
@@ -339,6 +485,90 @@ impl<Spec: MCTS> SearchComponent<Spec> for LookAhead<'_, Spec> {
     fn commit(&mut self, action_idx: usize) {
         self.invoker.commit(action_idx);
     }
+    fn share_commits(&mut self) {
+        self.invoker.share_commits();
+    }
+    fn action_stats(&self) -> Vec<ActionStats<Spec>> {
+        self.invoker.subcomponent.action_stats()
+    }
+}
+
+/// Nested Monte Carlo Search (NMCS, https://www.ijcai.org/Proceedings/09/Papers/083.pdf)
+/// of a given level. Level 0 is a rollout following a simulation policy. Level
+/// n tries each action with a level n-1 search, follows the first action of the
+/// best sequence found at this level, and repeats until the end.
+/// In terms of arXiv:1208.4692, this is 'step(lookahead(nested(n-1)))', but it
+/// can't be built from those components: they return the best sequence seen
+/// anywhere, while each level needs the best sequence under its own prefix.
+pub struct Nested<'a, Spec: MCTS> {
+    level: usize,
+    policy: Box<dyn SimulationPolicy<Spec> + 'a + Send + Sync>,
+    /// Best outcome of the top level, kept across steps like 'Simulate' does.
+    yielder: Yielder,
+}
+impl<'a, Spec: MCTS> Nested<'a, Spec> {
+    pub fn new(level: usize, policy: Box<dyn SimulationPolicy<Spec> + 'a + Send + Sync>) -> Self {
+        Self { level, policy, yielder: Yielder::new() }
+    }
+    /// Search at 'level', return the best outcome found (which starts with
+    /// 'decided').
+    fn nested(&mut self, level: usize, params: &mut SearchParams<Spec>,
+              state: &Spec::State, decided: Vec<usize>) -> Outcome {
+        let mut decided = decided;
+        let mut state = state.clone();
+        if level == 0 || params.state_is_done(&state, decided.len()) {
+            while !params.state_is_done(&state, decided.len()) {
+                let state_actions = state.generate_actions();
+                let action_idx = self.policy.pick_action(&state, &state_actions);
+                decided.push(action_idx);
+                state.apply_action(state_actions[action_idx].clone());
+            }
+            let score = params.evaluate(&state);
+            return Outcome { score, actions: decided, win: state.is_win() };
+        }
+        let mut best_outcome = Outcome::new();
+        while !params.state_is_done(&state, decided.len()) && !params.search_is_done()
+            && !best_outcome.win {
+            for (action_idx, action) in state.generate_actions().into_iter().enumerate() {
+                if params.search_is_done() { break; }
+                let mut next_state = state.clone();
+                next_state.apply_action(action);
+                let mut next_decided = decided.clone();
+                next_decided.push(action_idx);
+                best_outcome.update_best(
+                    self.nested(level - 1, params, &next_state, next_decided));
+            }
+            if best_outcome.actions.len() <= decided.len() {
+                // Over budget before trying any action.
+                break;
+            }
+            let action_idx = best_outcome.actions[decided.len()];
+            decided.push(action_idx);
+            let action = state.generate_actions()[action_idx].clone();
+            state.apply_action(action);
+        }
+        best_outcome
+    }
+}
+impl<Spec: MCTS> SearchComponent<Spec> for Nested<'_, Spec> {
+    fn execute(&mut self, params: &mut SearchParams<Spec>, state: &Spec::State,
+               decided: Vec<usize>) -> Outcome {
+        if params.search_is_done() || params.state_is_done(state, decided.len())
+            || self.yielder.best.win {
+            return self.yielder.best.clone();
+        }
+        let outcome = self.nested(self.level, params, state, decided);
+        self.yielder.best.update_best(outcome);
+        self.yielder.best.clone()
+    }
+    fn reset_prefix(&mut self, _decided: &Vec<usize>) {
+    }
+    fn commit(&mut self, action_idx: usize) {
+        self.yielder.advance(action_idx);
+    }
+    fn share_commits(&mut self) {
+        self.yielder.shared = true;
+    }
 }
 
 /// Non-uniform lookahead tree search, where outcomes of subsearches influence
@@ -415,6 +645,9 @@ impl<Spec: MCTS> SearchComponent<Spec> for Select<'_, Spec> {
                decided: Vec<usize>) -> Outcome {
         let mut decided = decided;
         let mut state = state.clone();
+        // Tree statistics are shared across determinizations, i.e. averaged
+        // over possible futures.
+        params.determinize(&mut state);
         let node = self.selection(&mut state, &mut decided, params);
         // Note: deliberately not calling our own reset here, to avoid the cost
         // (not needed, we know our 'start_node' didn't change).
@@ -434,6 +667,18 @@ impl<Spec: MCTS> SearchComponent<Spec> for Select<'_, Spec> {
         self.tree = Tree::new();
         self.start_node = self.tree.root;
     }
+    fn share_commits(&mut self) {
+        self.invoker.share_commits();
+    }
+    fn action_stats(&self) -> Vec<ActionStats<Spec>> {
+        self.tree.get(self.start_node).children.iter()
+            .filter(|child| child.visits > 0)
+            .map(|child| ActionStats {
+                action: child.action.clone(),
+                visits: child.visits,
+                avg_score: (child.score_sum / child.visits as f64) as Score,
+            }).collect()
+    }
 }
 
 
@@ -444,6 +689,8 @@ pub struct SearchParams<Spec: MCTS> {
     evaluator: Spec::Evaluator,
     stats: Stats,
     pub seed: u64,
+    // Source of seeds for determinizations of stochastic states.
+    determinization_rng: ChaCha8Rng,
     // If set, a rollout that lasts this many steps will be considered terminal
     max_rollout_length: Option<usize>,
     // Historical lowest/highest scores seen, used to scale rewards.
@@ -458,12 +705,19 @@ impl<Spec: MCTS> SearchParams<Spec> {
             budget,
             evaluator,
             seed,
+            determinization_rng: ChaCha8Rng::seed_from_u64(seed),
             stats: Stats::new(),
             max_rollout_length: None,
             lowest_score: 0.0,
             highest_score: 1.0,
         }
     }
+    /// Parameters to search with another seed, e.g. for parallel workers.
+    pub fn with_seed(&self, seed: u64) -> Self {
+        let mut params = Self::new(self.budget.clone(), self.evaluator.clone(), seed);
+        params.max_rollout_length = self.max_rollout_length;
+        params
+    }
     /// Consider a state terminal if its rollout length lasts this long.
     pub fn set_max_rollout_length(&mut self, length: usize) {
         self.max_rollout_length = Some(length);
@@ -476,6 +730,10 @@ impl<Spec: MCTS> SearchParams<Spec> {
         self.highest_score = self.highest_score.max(score);
         score
     }
+    /// Sample a new determinization of 'state', see 'SearchState::determinize'.
+    pub fn determinize(&mut self, state: &mut Spec::State) {
+        state.determinize(self.determinization_rng.gen());
+    }
     /// Return a score in the [0, 1] range, using historical high/lows.
     pub fn normalize_score(&self, score: Score) -> Score {
         assert!(score >= self.lowest_score);
@@ -500,10 +758,12 @@ impl<Spec: MCTS> SearchParams<Spec> {
 /// best outcome seen so far.
 struct Yielder {
     pub best: Outcome,
+    /// Whether commits can come from other parallel workers.
+    shared: bool,
 }
 impl Yielder {
     fn new() -> Self {
-        Self { best: Outcome::new() }
+        Self { best: Outcome::new(), shared: false }
     }
     fn yield_best<Spec: MCTS>(
         &mut self, params: &mut SearchParams<Spec>, state: &Spec::State,
@@ -513,12 +773,16 @@ impl Yielder {
         self.best.update_best(Outcome { score, actions, win: won });
         &self.best
     }
-    /// Advance the best sequence, ensuring the first action is indeed
-    /// 'action_idx'.
+    /// Advance the best sequence past 'action_idx', or forget it if another
+    /// parallel worker's pick made us go another way.
     fn advance(&mut self, action_idx: usize) {
-        assert!(!self.best.actions.is_empty());
-        assert_eq!(self.best.actions[0], action_idx);
-        self.best.actions.remove(0);
+        if self.best.actions.first() == Some(&action_idx) {
+            self.best.actions.remove(0);
+        } else {
+            debug_assert!(self.shared, "committed {} instead of our best {:?}",
+                          action_idx, self.best.actions.first());
+            self.best = Outcome::new();
+        }
     }
 }
 
@@ -540,12 +804,24 @@ impl<'a, Spec: MCTS> Invoker<'a, Spec> {
         // paper.
         self.subcomponent.execute(params, state, decided)
     }
+    /// Invoke on a new sample of the stochastic parts of 'state' (see
+    /// 'SearchState::determinize'), for components that iterate over
+    /// sub-searches.
+    fn invoke_determinized(&mut self, params: &mut SearchParams<Spec>, state: &Spec::State,
+                           decided: Vec<usize>) -> Outcome {
+        let mut state = state.clone();
+        params.determinize(&mut state);
+        self.invoke(params, &state, decided)
+    }
     fn reset_prefix(&mut self, decided: &Vec<usize>) {
         self.subcomponent.reset_prefix(decided);
     }
     fn commit(&mut self, action_idx: usize) {
         self.subcomponent.commit(action_idx);
     }
+    fn share_commits(&mut self) {
+        self.subcomponent.share_commits();
+    }
 }
 
 /// Selects the next child node to explore.
@@ -601,6 +877,7 @@ impl<Spec: MCTS> Tree<Spec> {
 // Budget implementations 
 
 /// Keep searching until we do N calls to the evaluator function.
+#[derive(Clone, Debug, PartialEq)]
 pub struct EvalCallsBudget {
     pub max_evals: usize,
 }
@@ -610,6 +887,7 @@ impl SearchBudget for EvalCallsBudget {
     }
 }
 /// Search for this much time at most.
+#[derive(Clone, Debug, PartialEq)]
 pub struct TimeBudget {
     pub max_time: Duration,
 }
@@ -619,6 +897,22 @@ impl SearchBudget for TimeBudget {
     }
 }
 
+/// Either a time or an evaluations budget, picked at runtime (e.g. from a bot
+/// spec).
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeOrEvalsBudget {
+    Time(TimeBudget),
+    EvalCalls(EvalCallsBudget),
+}
+impl SearchBudget for TimeOrEvalsBudget {
+    fn is_over_budget(&self, stats: &Stats) -> bool {
+        match self {
+            TimeOrEvalsBudget::Time(budget) => budget.is_over_budget(stats),
+            TimeOrEvalsBudget::EvalCalls(budget) => budget.is_over_budget(stats),
+        }
+    }
+}
+
 // TODO: RAM usage budget
 // TODO: Combination budget (combine CPU + RAM)
 
@@ -678,7 +972,7 @@ impl<'a, Spec: MCTS> GreedyPolicy<'a, Spec> {
 // Selector implementations
 
 /// UCB-1 selector, see https://arxiv.org/pdf/1208.4692 (6).
-struct Ucb1Selector {
+pub struct Ucb1Selector {
     /// Exploration parameter, 'c'.
     pub exploration: f32,
 }
@@ -761,3 +1055,161 @@ pub fn uct_algorithm_rollout<'a, Spec: MCTS + 'a>(
     let selector = Box::new(Ucb1Selector { exploration });
     mcts_algorithm(params, selector, rollout, step_iterations, state)
 }
+
+/// Nested Monte Carlo Search (NMCS) of the given level, with random rollouts.
+pub fn nmcs_algorithm<'a, Spec: MCTS + 'a>(
+    params: SearchParams<Spec>, level: usize, state: Spec::State) -> Algorithm<'a, Spec> {
+    let seed = params.seed;
+    let rollout = Box::new(RandomPolicy { rng: ChaCha8Rng::seed_from_u64(seed) });
+    nmcs_algorithm_rollout(params, level, state, rollout)
+}
+
+/// Nested Monte Carlo Search (NMCS) of the given level, with custom rollout.
+pub fn nmcs_algorithm_rollout<'a, Spec: MCTS + 'a>(
+    params: SearchParams<Spec>, level: usize, state: Spec::State,
+    rollout: Box<dyn SimulationPolicy<Spec> + 'a + Send + Sync>) -> Algorithm<'a, Spec> {
+    let nested = Box::new(Nested::new(level, rollout));
+    Algorithm::new(nested, params, state)
+}
+
+/// Root parallelization (Chaslot et al., "Parallel Monte-Carlo Tree Search",
+/// 2008): run 'num_workers' independent copies of an algorithm on separate
+/// threads, each with its own tree, and merge their root statistics to pick
+/// moves. The first worker uses 'params.seed', like the algorithm would on
+/// its own, others use seeds derived from it. Each worker gets the full
+/// budget.
+pub fn root_parallel_algorithm<'a, Spec: MCTS + 'a>(
+    params: SearchParams<Spec>, num_workers: usize, state: Spec::State,
+    make_algorithm: impl Fn(SearchParams<Spec>, Spec::State) -> Algorithm<'a, Spec>)
+    -> Algorithm<'a, Spec> {
+    assert!(num_workers > 0);
+    let mut seeds = ChaCha8Rng::seed_from_u64(params.seed);
+    let worker_params: Vec<_> = (1..num_workers).map(|_| params.with_seed(seeds.gen())).collect();
+    let mut algorithm = make_algorithm(params, state.clone());
+    for params in worker_params {
+        algorithm.workers.extend(make_algorithm(params, state.clone()).workers);
+    }
+    if algorithm.workers.len() > 1 {
+        for worker in &mut algorithm.workers {
+            worker.component.share_commits();
+        }
+    }
+    algorithm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walk on a line for a few steps, the further right the better.
+    #[derive(Clone)]
+    struct LineState {
+        x: i32,
+        steps: usize,
+    }
+    impl SearchState<LineSpec> for LineState {
+        fn generate_actions(&self) -> Vec<i32> {
+            vec![-1, 0, 1]
+        }
+        fn apply_action(&mut self, action: i32) {
+            self.x += action;
+            self.steps += 1;
+        }
+        fn is_terminal(&self) -> bool {
+            self.steps >= 10
+        }
+        fn is_win(&self) -> bool {
+            false
+        }
+    }
+    #[derive(Clone)]
+    struct RightIsBetter;
+    impl Evaluator<LineSpec> for RightIsBetter {
+        fn evaluate(&self, state: &LineState) -> Score {
+            state.x as Score
+        }
+    }
+    struct LineSpec;
+    impl MCTS for LineSpec {
+        type Action = i32;
+        type State = LineState;
+        type ActionSpace = Vec<i32>;
+        type Evaluator = RightIsBetter;
+        type Budget = EvalCallsBudget;
+    }
+
+    fn root_parallel(seed: u64) -> Algorithm<'static, LineSpec> {
+        let params = SearchParams::new(EvalCallsBudget { max_evals: 200 }, RightIsBetter, seed);
+        root_parallel_algorithm(params, 3, LineState { x: 0, steps: 0 }, |params, state| {
+            uct_algorithm(params, 2_f32.sqrt(), 20, state)
+        })
+    }
+
+    #[test]
+    fn test_nmcs_algorithm() {
+        let params = SearchParams::<LineSpec>::new(
+            EvalCallsBudget { max_evals: 20000 }, RightIsBetter, 42);
+        let mut algorithm = nmcs_algorithm(params, 2, LineState { x: 0, steps: 0 });
+        let results = algorithm.search();
+        assert_eq!(results.next_action, Some(1));
+        assert_eq!(results.best_sequence, vec![1; 10]);
+        // Keeps its best sequence after committing to the first action, even
+        // without budget to search again.
+        let mut params = SearchParams::<LineSpec>::new(
+            EvalCallsBudget { max_evals: 0 }, RightIsBetter, 42);
+        let mut nested = Nested::new(1, Box::new(RandomPolicy { rng: ChaCha8Rng::seed_from_u64(0) }));
+        let state = LineState { x: 0, steps: 0 };
+        assert!(nested.execute(&mut params, &state, Vec::new()).is_empty());
+        params.budget.max_evals = 1000;
+        let outcome = nested.execute(&mut params, &state, Vec::new());
+        assert_eq!(outcome.actions.len(), 10);
+        nested.commit(outcome.actions[0]);
+        params.budget.max_evals = 0;
+        assert_eq!(nested.execute(&mut params, &state, Vec::new()).actions,
+                   outcome.actions[1..].to_vec());
+    }
+
+    #[test]
+    fn test_determinize_per_iteration() {
+        let state = LineState { x: 0, steps: 0 };
+        let simulate = || Box::new(Simulate::new(
+            Box::new(RandomPolicy { rng: ChaCha8Rng::seed_from_u64(0) })));
+        // Each iteration searches a new sample of the future, so that
+        // statistics are averaged over futures. A sample uses 2 words.
+        let mut params = SearchParams::<LineSpec>::new(
+            EvalCallsBudget { max_evals: 1000 }, RightIsBetter, 42);
+        Repeat::new(10, simulate()).execute(&mut params, &state, Vec::new());
+        assert_eq!(params.determinization_rng.get_word_pos(), 10 * 2);
+        let mut params = SearchParams::<LineSpec>::new(
+            EvalCallsBudget { max_evals: 1000 }, RightIsBetter, 42);
+        let select = Select::new(Box::new(Ucb1Selector { exploration: 1.0 }), simulate());
+        Step::new(Box::new(Repeat::new(5, Box::new(select))))
+            .execute(&mut params, &state, Vec::new());
+        assert!(params.stats.num_evals > 10);
+        assert!(params.determinization_rng.get_word_pos() > 2 * params.stats.num_evals as u128);
+    }
+
+    #[test]
+    fn test_root_parallel_algorithm() {
+        let mut algorithm = root_parallel(42);
+        let mut again = root_parallel(42);
+        for _ in 0..3 {
+            let results = algorithm.search();
+            // Workers all spend their budget, and their statistics are merged.
+            assert!(results.stats.num_evals >= 3 * 200);
+            assert_eq!(results.action_stats.len(), 3);
+            let most_visited = results.action_stats.iter().max_by_key(|stats| stats.visits);
+            assert_eq!(results.next_action, most_visited.map(|stats| stats.action));
+            // Deterministic for a seed.
+            let other = again.search();
+            assert_eq!(other.best_sequence, results.best_sequence);
+            assert_eq!(other.stats.num_evals, results.stats.num_evals);
+        }
+        assert!(algorithm.state.x > 0);
+        // Actions that no worker visited don't poison the merged averages.
+        let unvisited = || vec![ActionStats { action: 1, visits: 0, avg_score: 0.0 }];
+        let (_, merged) = algorithm.merge_workers(
+            vec![Outcome::new(), Outcome::new()], vec![unvisited(), unvisited()]);
+        assert!(merged.is_empty());
+    }
+}
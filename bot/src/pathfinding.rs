@@ -1,5 +1,6 @@
 use std::cmp::Reverse;
 use std::collections::VecDeque;
+use std::sync::OnceLock;
 
 use crate::grid::{EmptyTile, Grid, Move, Pos};
 
@@ -201,55 +202,126 @@ impl Pathfinder for FastAggressivePathfinder {
     }
 }
 
-/// Grid that precomputes pathfinding information at creation time.
+/// Grids with at least this many empty tiles default to the 'Lazy' backend.
+pub const LAZY_BACKEND_MIN_TILES: usize = 2000;
+
+/// How a 'PathfindingGrid' gets its all-pairs pathfinding information.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathfindingBackend {
+    /// Pathfind from every empty tile at creation time. Lookups never have to
+    /// wait, but memory and startup time are quadratic in the number of tiles.
+    Precomputed,
+    /// Pathfind from an empty tile the first time it is needed, then cache it.
+    /// Games only ever look at a fraction of the tiles, which makes this
+    /// usable on large maps.
+    Lazy,
+}
+
+impl PathfindingBackend {
+    /// Backend to use by default for a grid, based on its size.
+    pub fn for_grid(grid: &Grid) -> Self {
+        if grid.empty_tiles.len() >= LAZY_BACKEND_MIN_TILES {
+            PathfindingBackend::Lazy
+        } else {
+            PathfindingBackend::Precomputed
+        }
+    }
+}
+
+/// Pathfinding information from a single empty tile.
+struct PathfindingRow {
+    state: PathfinderState,
+    /// Per-empty-tile target, next move from this tile when code is using
+    /// 'aggressive.js' pathfinding.
+    aggressive_moves: Vec<Option<Move>>,
+    /// Per-empty-tile source, next move towards this tile when code is using
+    /// 'utils/pathfinding.js'.
+    pathfinding_moves: Vec<Option<Move>>,
+}
+
+impl PathfindingRow {
+    fn new(grid: &Grid, from: &Pos) -> Self {
+        // Note: deliberate using the 'aggressive.js' version of pathfinding
+        // for fast 'get_aggressive_path' lookups.
+        let mut pathfinder = FastAggressivePathfinder::new(grid);
+        let state = pathfinder.pathfind(grid, from, &None);
+        let from_idx = grid.empty_tile_idx(from);
+
+        // The first move towards a tile is the first move towards its parent,
+        // so visit tiles by increasing cost.
+        let mut aggressive_moves = vec![None; grid.empty_tiles.len()];
+        let mut by_cost: Vec<Node> = (0..grid.empty_tiles.len())
+            .filter(|&node| state.cost[node] != COST_INFINITY)
+            .collect();
+        by_cost.sort_by_key(|&node| state.cost[node]);
+        for node in by_cost {
+            aggressive_moves[node] = match state.came_from[node] {
+                Some(parent) if parent == from_idx =>
+                    follow_path(from, &vec![grid.empty_tiles[node]]),
+                Some(parent) => aggressive_moves[parent],
+                None => None,
+            };
+        }
+
+        // Costs are symmetric, so this row also tells every tile which of its
+        // neighbors gets closer to us. See 'PathfindingGrid::get_path'.
+        let pathfinding_moves = grid.empty_tiles.iter().enumerate().map(|(node, pos)| {
+            let cost = state.cost[node];
+            if cost == 0 || cost == COST_INFINITY {
+                return None;
+            }
+            [Move::Left, Move::Right, Move::Up, Move::Down].into_iter().find(|&d| {
+                let next = pos.moved(d);
+                grid.is_empty(&next) && state.get_cost(grid, &next) == cost - 1
+            })
+        }).collect();
+
+        Self { state, aggressive_moves, pathfinding_moves }
+    }
+}
+
+/// Grid that provides pathfinding information between any two empty tiles,
+/// computed at creation time or on first use (see 'PathfindingBackend').
 pub struct PathfindingGrid {
     pub grid: Grid,
-    /// Per-empty tile precomputed pathfinding information.
-    pathfinding_states: Vec<PathfinderState>,
-    /// For each empty tile source, per-empty-tile target next move, when code
-    /// is using 'utils/pathfinding.js'.
-    move_lookup_pathfinding: Vec<Vec<Option<Move>>>,
-    /// For each empty tile source, per-empty-tile target next move, when code
-    /// is using 'aggressive.js' pathfinding.
-    move_lookup_aggressive: Vec<Vec<Option<Move>>>,
+    backend: PathfindingBackend,
+    /// Per-empty tile pathfinding information, filled on creation for the
+    /// 'Precomputed' backend.
+    rows: Vec<OnceLock<PathfindingRow>>,
 }
 
 impl PathfindingGrid {
     pub fn new(grid: Grid) -> Self {
-        let mut pathfinding_states = Vec::new();
-        for pos in &grid.empty_tiles {
-            // Note: deliberate using the 'aggressive.js' version of pathfinding
-            // for fast 'get_aggressive_path' lookups.
-            let mut pathfinder = FastAggressivePathfinder::new(&grid);
-            pathfinding_states.push(pathfinder.pathfind(&grid, &pos, &None));
-        }
-        let mut precomputed = Self {
-            grid, pathfinding_states,
-            // The following are easier to compute using methods.
-            move_lookup_pathfinding: Vec::new(),
-            move_lookup_aggressive: Vec::new(),
+        let backend = PathfindingBackend::for_grid(&grid);
+        Self::with_backend(grid, backend)
+    }
+
+    pub fn with_backend(grid: Grid, backend: PathfindingBackend) -> Self {
+        let rows = match backend {
+            PathfindingBackend::Precomputed => grid.empty_tiles.iter()
+                .map(|pos| OnceLock::from(PathfindingRow::new(&grid, pos)))
+                .collect(),
+            PathfindingBackend::Lazy => (0..grid.empty_tiles.len())
+                .map(|_| OnceLock::new())
+                .collect(),
         };
-        for from in &precomputed.grid.empty_tiles {
-            let mut pathfinding_lookup = Vec::new();
-            let mut aggressive_lookup = Vec::new();
-            for to in &precomputed.grid.empty_tiles {
-                pathfinding_lookup.push(
-                    follow_path(from, &precomputed.get_path(from, to)));
-                aggressive_lookup.push(
-                    follow_path(from, &precomputed.get_aggressive_path(from, to)));
-            }
-            precomputed.move_lookup_pathfinding.push(pathfinding_lookup);
-            precomputed.move_lookup_aggressive.push(aggressive_lookup);
-        }
-        precomputed
+        Self { grid, backend, rows }
+    }
+
+    pub fn backend(&self) -> PathfindingBackend {
+        self.backend
+    }
+
+    fn row(&self, from: &Pos) -> &PathfindingRow {
+        let from_idx = self.grid.empty_tile_idx(from);
+        self.rows[from_idx].get_or_init(|| PathfindingRow::new(&self.grid, from))
     }
 
     /// Get path following the logic of utils/pathfinding.js
     /// Reference:
     /// https://github.com/JesseEmond/blitz-2025-registration/blob/bb2ec9d263ebeb65e09230506d55a25111a2f48b/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/utils/pathfinding.decomp.js#L411-L419
     pub fn get_path(&self, from: &Pos, to: &Pos) -> Vec<Pos> {
-        let to_idx = self.grid.empty_tile_idx(to);
-        let to_state = &self.pathfinding_states[to_idx];
+        let to_state = &self.row(to).state;
         let mut cost = to_state.get_cost(&self.grid, from);
         if cost == COST_INFINITY {
             return Vec::new();
@@ -274,25 +346,21 @@ impl PathfindingGrid {
 
     /// Get path the same way that 'aggressive.js' would find it.
     pub fn get_aggressive_path(&self, from: &Pos, to: &Pos) -> Vec<Pos> {
-        let from_idx = self.grid.empty_tile_idx(from);
-        self.pathfinding_states[from_idx].get_path(&self.grid, to)
+        self.row(from).state.get_path(&self.grid, to)
     }
 
     pub fn get_cost(&self, from: &Pos, to: &Pos) -> Cost {
-        let from_idx = self.grid.empty_tile_idx(from);
-        self.pathfinding_states[from_idx].get_cost(&self.grid, to)
+        self.row(from).state.get_cost(&self.grid, to)
     }
 
     pub fn get_pathfinding_next_move(&self, from: &Pos, to: &Pos) -> Option<Move> {
         let from_idx = self.grid.empty_tile_idx(from);
-        let to_idx = self.grid.empty_tile_idx(to);
-        self.move_lookup_pathfinding[from_idx][to_idx]
+        self.row(to).pathfinding_moves[from_idx]
     }
 
     pub fn get_aggressive_next_move(&self, from: &Pos, to: &Pos) -> Option<Move> {
-        let from_idx = self.grid.empty_tile_idx(from);
         let to_idx = self.grid.empty_tile_idx(to);
-        self.move_lookup_aggressive[from_idx][to_idx]
+        self.row(from).aggressive_moves[to_idx]
     }
 }
 
@@ -341,6 +409,37 @@ mod tests {
         ])
     }
 
+    #[test]
+    fn test_backends_match_paths() {
+        // Also check unreachable tiles, with two disconnected rooms.
+        let rooms = make_grid(vec![
+            "#######",
+            "#  #  #",
+            "# ##  #",
+            "#######",
+        ]);
+        for grid in [make_test_grid(), rooms] {
+            let precomputed = PathfindingGrid::with_backend(
+                grid.clone(), PathfindingBackend::Precomputed);
+            let lazy = PathfindingGrid::with_backend(grid.clone(), PathfindingBackend::Lazy);
+            for from in &grid.empty_tiles {
+                for to in &grid.empty_tiles {
+                    let path = precomputed.get_path(from, to);
+                    let aggressive_path = precomputed.get_aggressive_path(from, to);
+                    assert_eq!(lazy.get_path(from, to), path);
+                    assert_eq!(lazy.get_aggressive_path(from, to), aggressive_path);
+                    assert_eq!(lazy.get_cost(from, to), precomputed.get_cost(from, to));
+                    for pathfinding in [&precomputed, &lazy] {
+                        assert_eq!(pathfinding.get_pathfinding_next_move(from, to),
+                                   follow_path(from, &path));
+                        assert_eq!(pathfinding.get_aggressive_next_move(from, to),
+                                   follow_path(from, &aggressive_path));
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_slow_pathfinder_same_path_as_fast_pathfinder() {
         let grid = make_test_grid();
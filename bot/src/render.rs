@@ -0,0 +1,239 @@
+/// Render games to images (per-tick PNG frames or an animated GIF), to review
+/// games without the official UI.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgb, RgbImage};
+
+use crate::grid::Pos;
+use crate::map_loader::threat_color;
+use crate::simulation::{SimulationAction, State, Style, ThreatStorage};
+
+const WALL_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+const EMPTY_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+const PLAYER_COLOR: Rgb<u8> = Rgb([0, 0, 255]);
+const DEAD_PLAYER_COLOR: Rgb<u8> = Rgb([125, 0, 125]);
+const UNKNOWN_THREAT_COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+
+pub type Result<T> = std::result::Result<T, RenderError>;
+
+#[derive(Debug)]
+pub enum RenderError {
+    FileError(std::io::Error),
+    EncodingError(image::ImageError),
+    NoFrames,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// Size of a tile, in pixels.
+    pub tile_size: u32,
+    /// Show where Owls and Hawks are headed.
+    pub show_targets: bool,
+    /// If set, show where threats will be in the next N ticks, if the player
+    /// stays in place.
+    pub predicted_ticks: Option<usize>,
+    /// Time each frame is shown in GIFs.
+    pub frame_delay_ms: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            tile_size: 8,
+            show_targets: false,
+            predicted_ticks: None,
+            frame_delay_ms: 100,
+        }
+    }
+}
+
+/// Draw the state of a single tick.
+pub fn render_frame(state: &State, options: &RenderOptions) -> RgbImage {
+    let grid = &state.grid.grid;
+    let (width, height) = grid.dims();
+    let size = options.tile_size;
+    let mut image = RgbImage::new(width as u32 * size, height as u32 * size);
+    for x in 0..width as i16 {
+        for y in 0..height as i16 {
+            let pos = Pos { x, y };
+            let color = if grid.is_empty(&pos) { EMPTY_COLOR } else { WALL_COLOR };
+            fill_tile(&mut image, &pos, color, size);
+        }
+    }
+    if let Some(ticks) = options.predicted_ticks {
+        let mut predicted = state.clone();
+        for _ in 0..ticks {
+            if predicted.game_over { break; }
+            predicted.simulate_tick(SimulationAction::Move { direction: None });
+            for threat in &predicted.threats {
+                fill_center(&mut image, &threat.pos, lighten(color_of(threat.style)),
+                            size, size / 4);
+            }
+        }
+    }
+    if options.show_targets {
+        for threat in &state.threats {
+            let target = match &threat.storage {
+                Some(ThreatStorage::Owl { target_last_seen }) => Some(*target_last_seen),
+                Some(ThreatStorage::Hawk { target_last_seen, idle_position, .. }) =>
+                    target_last_seen.or(*idle_position),
+                None => None,
+            };
+            if let Some(target) = target {
+                outline_tile(&mut image, &target, color_of(threat.style), size);
+            }
+        }
+    }
+    for threat in &state.threats {
+        fill_tile(&mut image, &threat.pos, color_of(threat.style), size);
+    }
+    let player_color = if state.check_game_over() && !state.player_won() {
+        DEAD_PLAYER_COLOR
+    } else {
+        PLAYER_COLOR
+    };
+    fill_center(&mut image, &state.pos, player_color, size, size / 2);
+    image
+}
+
+/// Save each state as 'frame_<tick>.png' in 'dir'.
+pub fn save_frames(states: &[State], dir: &Path, options: &RenderOptions) -> Result<()> {
+    if states.is_empty() {
+        return Err(RenderError::NoFrames);
+    }
+    fs::create_dir_all(dir).map_err(RenderError::FileError)?;
+    for state in states {
+        let path = dir.join(format!("frame_{:04}.png", state.tick));
+        render_frame(state, options).save(path).map_err(RenderError::EncodingError)?;
+    }
+    Ok(())
+}
+
+/// Save states as an animated GIF, one frame per state.
+pub fn save_gif(states: &[State], path: &Path, options: &RenderOptions) -> Result<()> {
+    if states.is_empty() {
+        return Err(RenderError::NoFrames);
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(RenderError::FileError)?;
+    }
+    let file = File::create(path).map_err(RenderError::FileError)?;
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), /*speed=*/10);
+    encoder.set_repeat(Repeat::Infinite).map_err(RenderError::EncodingError)?;
+    let delay = Delay::from_numer_denom_ms(options.frame_delay_ms, 1);
+    let frames = states.iter().map(|state| {
+        let image = image::DynamicImage::ImageRgb8(render_frame(state, options)).to_rgba8();
+        Frame::from_parts(image, 0, 0, delay)
+    });
+    encoder.encode_frames(frames).map_err(RenderError::EncodingError)
+}
+
+/// Save states as a GIF if 'path' ends in '.gif', or PNG frames in the 'path'
+/// directory otherwise.
+pub fn save(states: &[State], path: &Path, options: &RenderOptions) -> Result<()> {
+    if path.extension().is_some_and(|ext| ext == "gif") {
+        save_gif(states, path, options)
+    } else {
+        save_frames(states, path, options)
+    }
+}
+
+fn color_of(style: Style) -> Rgb<u8> {
+    threat_color(style).unwrap_or(UNKNOWN_THREAT_COLOR)
+}
+
+fn lighten(color: Rgb<u8>) -> Rgb<u8> {
+    Rgb(color.0.map(|c| c / 2 + 128))
+}
+
+/// Fill a square of 'size' pixels in the middle of a tile.
+fn fill_center(image: &mut RgbImage, pos: &Pos, color: Rgb<u8>, tile_size: u32,
+               size: u32) {
+    let size = size.clamp(1, tile_size);
+    let offset = (tile_size - size) / 2;
+    let (x0, y0) = (pos.x as u32 * tile_size + offset, pos.y as u32 * tile_size + offset);
+    fill_rect(image, x0, y0, size, color);
+}
+
+fn fill_tile(image: &mut RgbImage, pos: &Pos, color: Rgb<u8>, size: u32) {
+    fill_rect(image, pos.x as u32 * size, pos.y as u32 * size, size, color);
+}
+
+fn outline_tile(image: &mut RgbImage, pos: &Pos, color: Rgb<u8>, size: u32) {
+    let (x0, y0) = (pos.x as u32 * size, pos.y as u32 * size);
+    for i in 0..size {
+        for (x, y) in [(x0 + i, y0), (x0 + i, y0 + size - 1),
+                       (x0, y0 + i), (x0 + size - 1, y0 + i)] {
+            put_pixel(image, x, y, color);
+        }
+    }
+}
+
+fn fill_rect(image: &mut RgbImage, x0: u32, y0: u32, size: u32, color: Rgb<u8>) {
+    for x in x0..x0 + size {
+        for y in y0..y0 + size {
+            put_pixel(image, x, y, color);
+        }
+    }
+}
+
+fn put_pixel(image: &mut RgbImage, x: u32, y: u32, color: Rgb<u8>) {
+    if x < image.width() && y < image.height() {
+        image.put_pixel(x, y, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::make_grid;
+    use crate::simulation::{Game, Threat};
+    use image::AnimationDecoder;
+    use image::codecs::gif::GifDecoder;
+
+    fn make_state() -> State {
+        let grid = make_grid(vec![
+            "#####",
+            "#   #",
+            "#   #",
+            "#####",
+        ]);
+        let threats = vec![Threat::spawn(Pos { x: 3, y: 2 }, Style::Bull)];
+        State::new(Game { tick: 1, pos: Pos { x: 1, y: 1 }, grid, threats, alive: true })
+    }
+
+    #[test]
+    fn test_render_frame() {
+        let options = RenderOptions { tile_size: 4, ..RenderOptions::default() };
+        let image = render_frame(&make_state(), &options);
+        assert_eq!(image.dimensions(), (20, 16));
+        assert_eq!(*image.get_pixel(0, 0), WALL_COLOR);
+        assert_eq!(*image.get_pixel(9, 9), EMPTY_COLOR);
+        // Player in the middle of its tile.
+        assert_eq!(*image.get_pixel(5, 5), PLAYER_COLOR);
+        assert_eq!(*image.get_pixel(4, 4), EMPTY_COLOR);
+        assert_eq!(*image.get_pixel(12, 8), threat_color(Style::Bull).unwrap());
+    }
+
+    #[test]
+    fn test_save_gif() {
+        let mut state = make_state();
+        let mut states = vec![state.clone()];
+        for _ in 0..3 {
+            state.simulate_tick(SimulationAction::Move { direction: None });
+            states.push(state.clone());
+        }
+        let path = std::env::temp_dir().join(
+            format!("devnull_render_test_{}.gif", std::process::id()));
+        save(&states, &path, &RenderOptions::default()).unwrap();
+        let decoder = GifDecoder::new(std::io::BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!(decoder.into_frames().count(), states.len());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(save(&[], &path, &RenderOptions::default()),
+                         Err(RenderError::NoFrames)));
+    }
+}
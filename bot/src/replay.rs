@@ -0,0 +1,195 @@
+/// Offline replay of games saved with 'SAVE_JSONL_PATH' (see 'application.py'),
+/// to check that our simulation matches the server's.
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::game_message::{self, validate_actions, Action, TeamGameState};
+use crate::simulation::{Game, GameOptions, Misprediction, State};
+
+pub type Result<T> = std::result::Result<T, ReplayError>;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    FileLoadingError(std::io::Error),
+    /// Line (1-indexed) that is not a valid saved tick.
+    ParseError { line: usize, error: serde_json::Error },
+    MessageError { line: usize, error: game_message::MessageError },
+    /// We only ever send a single action per tick.
+    TooManyActions { line: usize, count: usize },
+    EmptyReplay,
+}
+
+/// One line of a saved game.
+#[derive(Deserialize)]
+struct StoredTick {
+    game_message: TeamGameState,
+    actions: Vec<Action>,
+}
+
+/// Tick of a saved game: what the server sent, and what we did.
+#[derive(Clone)]
+pub struct SavedTick {
+    pub game: Game,
+    pub action: Option<Action>,
+}
+
+/// First tick where our simulation did not match the saved game.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    pub tick: usize,
+    pub mispredictions: Vec<Misprediction>,
+}
+
+pub struct ReplayReport {
+    /// Saved ticks that matched our simulation.
+    pub ticks_matched: usize,
+    pub divergence: Option<Divergence>,
+}
+
+pub fn load_replay(path: &Path) -> Result<Vec<SavedTick>> {
+    let contents = fs::read_to_string(path)
+        .map_err(ReplayError::FileLoadingError)?;
+    parse_replay(&contents)
+}
+
+/// Parse saved ticks, one JSON object per line.
+pub fn parse_replay(jsonl: &str) -> Result<Vec<SavedTick>> {
+    let ticks = jsonl.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let line_number = i + 1;
+            let stored: StoredTick = serde_json::from_str(line)
+                .map_err(|error| ReplayError::ParseError { line: line_number, error })?;
+            if stored.actions.len() > 1 {
+                return Err(ReplayError::TooManyActions {
+                    line: line_number, count: stored.actions.len() });
+            }
+            let game = stored.game_message.to_game()
+                .map_err(|error| ReplayError::MessageError { line: line_number, error })?;
+            Ok(SavedTick { game, action: stored.actions.into_iter().next() })
+        }).collect::<Result<Vec<_>>>()?;
+    if ticks.is_empty() {
+        return Err(ReplayError::EmptyReplay);
+    }
+    Ok(ticks)
+}
+
+/// Simulate the saved game from its first tick, applying the saved actions,
+/// and compare our predictions to every tick the server sent.
+pub fn replay(ticks: &[SavedTick], options: GameOptions) -> Result<ReplayReport> {
+    let first = ticks.first().ok_or(ReplayError::EmptyReplay)?;
+    let mut state = State::new_custom(first.game.clone(), options);
+    for (i, tick) in ticks.iter().enumerate() {
+        let mispredictions = state.find_mispredictions(&tick.game);
+        if !mispredictions.is_empty() {
+            return Ok(ReplayReport {
+                ticks_matched: i,
+                divergence: Some(Divergence { tick: tick.game.tick, mispredictions }),
+            });
+        }
+        // Invalid actions are ignored by the server, too.
+        let action = validate_actions(&state, tick.action.as_slice(), &mut Vec::new());
+        state.simulate_tick(action);
+    }
+    Ok(ReplayReport { ticks_matched: ticks.len(), divergence: None })
+}
+
+/// State of a saved tick, as seen by our simulation.
+#[derive(Clone)]
+pub struct ReplayedState {
+    pub state: State,
+    /// Differences between our prediction and the saved tick, before resyncing.
+    pub mispredictions: Vec<Misprediction>,
+}
+
+/// States of the saved game as seen by our simulation, e.g. to render it.
+/// Hidden state (e.g. threat targets) comes from our simulation, which is
+/// resynced with the saved game whenever it diverges.
+pub fn replay_states(ticks: &[SavedTick], options: GameOptions) -> Result<Vec<ReplayedState>> {
+    let first = ticks.first().ok_or(ReplayError::EmptyReplay)?;
+    let mut state = State::new_custom(first.game.clone(), options);
+    let mut states = Vec::with_capacity(ticks.len() + 1);
+    for tick in ticks {
+        let mispredictions = state.find_mispredictions(&tick.game);
+        if !mispredictions.is_empty() {
+            state.resync(&tick.game);
+        }
+        states.push(ReplayedState { state: state.clone(), mispredictions });
+        let action = validate_actions(&state, tick.action.as_slice(), &mut Vec::new());
+        state.simulate_tick(action);
+    }
+    // Also show how the game ended.
+    states.push(ReplayedState { state, mispredictions: Vec::new() });
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{make_grid, Move, Pos};
+    use crate::simulation::{Style, Threat};
+
+    /// Save a game the way 'application.py' does, by simulating it ourselves.
+    fn save_game(num_ticks: usize) -> Vec<String> {
+        let grid = make_grid(vec![
+            "#########",
+            "#       #",
+            "# ## ## #",
+            "#       #",
+            "#########",
+        ]);
+        let threats = vec![
+            Threat::spawn(Pos { x: 1, y: 1 }, Style::Goldfish),
+            Threat::spawn(Pos { x: 7, y: 1 }, Style::Bull),
+        ];
+        let game = Game { tick: 1, pos: Pos { x: 4, y: 3 }, grid, threats, alive: true };
+        let mut state = State::new(game);
+        let moves = [Some(Move::Left), Some(Move::Right), None];
+        (0..num_ticks).map(|i| {
+            let action = Action::from_move(moves[i % moves.len()]);
+            let line = serde_json::json!({
+                "game_message": TeamGameState::from_game(&state.to_game()),
+                "actions": action.iter().collect::<Vec<_>>(),
+            }).to_string();
+            state.simulate_tick(Action::to_simulation_action(action.as_ref()));
+            line
+        }).collect()
+    }
+
+    #[test]
+    fn test_replay_matches_simulation() {
+        let lines = save_game(20);
+        let ticks = parse_replay(&lines.join("\n")).unwrap();
+        assert_eq!(ticks.len(), 20);
+        assert_eq!(ticks[0].action, Some(Action::MoveLeft));
+        assert_eq!(ticks[2].action, None);
+        let report = replay(&ticks, GameOptions::default()).unwrap();
+        assert!(report.divergence.is_none());
+        assert_eq!(report.ticks_matched, 20);
+    }
+
+    #[test]
+    fn test_replay_reports_first_divergence() {
+        let mut ticks = parse_replay(&save_game(20).join("\n")).unwrap();
+        let tick = ticks[10].game.tick;
+        ticks[10].game.threats[1].pos = Pos { x: 0, y: 0 };
+        let report = replay(&ticks, GameOptions::default()).unwrap();
+        assert_eq!(report.ticks_matched, 10);
+        let divergence = report.divergence.unwrap();
+        assert_eq!(divergence.tick, tick);
+        assert!(matches!(divergence.mispredictions[0],
+                         Misprediction::ThreatPos { threat: 1, .. }));
+    }
+
+    #[test]
+    fn test_parse_replay_errors() {
+        assert!(matches!(parse_replay(""), Err(ReplayError::EmptyReplay)));
+        let lines = save_game(2);
+        let corrupted = format!("{}\n{{not json", lines[0]);
+        assert!(matches!(parse_replay(&corrupted),
+                         Err(ReplayError::ParseError { line: 2, .. })));
+    }
+}
@@ -1,11 +1,18 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
 use clap::ValueEnum;
+use itertools::Itertools;
 use smallvec::SmallVec;
 
+use crate::bot_spec::BotSpec;
+use crate::grammar;
 use crate::grid::{Move, Pos};
+use crate::inference;
 use crate::mcts;
-use crate::simulation::{Game, SimulationAction, State};
+use crate::simulation::{Game, Misprediction, SimulationAction, State, Style};
 
-/// Available bot algorithms to use.
+/// Preset bots, see 'BotSpec::preset' for their algorithms.
 #[derive(ValueEnum, Clone, Debug, Copy)]
 pub enum BotName {
     /// Use a random sampling search algorithm for the duration of the budget.
@@ -24,52 +31,30 @@ pub enum BotName {
     /// prioritizes the ones that have the highest number of near enemies, to
     /// make the bot look like it's surviving in extra dangerous situations.
     ShowOff,
+    /// Like Uct100RolloutsSqrt2CGreedyNotDead, but 4 searches run on separate
+    /// threads and vote on each move with their statistics (root
+    /// parallelization).
+    RootParallelUct100RolloutsSqrt2CGreedyNotDead,
+    /// Like ShowOff, but with root parallelization over 4 threads.
+    RootParallelShowOff,
+    /// Nested Monte Carlo Search of level 1: try each move followed by a
+    /// rollout, play the best one, repeat. Rollouts are greedy with a 'do not
+    /// die' heuristic.
+    Nmcs1GreedyNotDead,
+    /// Nested Monte Carlo Search of level 2, i.e. like Nmcs1GreedyNotDead, but
+    /// moves are tried with a level 1 search instead of a rollout.
+    Nmcs2GreedyNotDead,
 }
 
 /// Best bot we have / preferred bot. Default bot used on initialization.
 // Not really the best search-wise, but gives cooler games.
-const BEST_BOT: BotName = BotName::ShowOff;
-
-impl BotName {
-    fn make_algorithm<'a>(
-        self, state: State, params: mcts::SearchParams<MCTS>) -> mcts::Algorithm<'a, MCTS> {
-        let seed = params.seed;
-        match self {
-            BotName::Sampling => mcts::sampling_algorithm(params, state),
-            BotName::IterativeSampling100 => mcts::iterative_sampling_algorithm(params, 100, state),
-            BotName::Uct100RolloutsSqrt2C => mcts::uct_algorithm(params, 2_f32.sqrt(), 100, state),
-            BotName::Uct100RolloutsSqrt2CGreedyNotDead => mcts::uct_algorithm_rollout(
-                params, 2_f32.sqrt(), 100, state,
-                Box::new(mcts::GreedyPolicy::new(seed, Box::new(NotDeadEval {})))),
-            BotName::ShowOff => mcts::uct_algorithm_rollout(
-                params, 2_f32.sqrt(), 100, state,
-                Box::new(mcts::GreedyPolicy::new(seed, Box::new(NotDeadShowOffEval {})))),
-        }
-    }
-}
+pub const BEST_BOT: BotName = BotName::ShowOff;
 
 // Note that below we implement mcts traits for more than just this file's
 // "MCTS", to allow reuse with variants of "MCTS"
 // (e.g. in unit tests/benchmarks that use a different search budget).
 
-/// Example eval where our both should move right when it can.
-struct MoveRightEval;
-impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for MoveRightEval {
-    fn evaluate(&self, state: &Spec::State) -> mcts::Score {
-        state.pos.x as mcts::Score
-    }
-}
-struct MoveRightAliveEval;
-impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for MoveRightAliveEval {
-    fn evaluate(&self, state: &Spec::State) -> mcts::Score {
-        if !state.game_over {
-            state.pos.x as mcts::Score
-        } else {
-            -50000 as mcts::Score + state.tick as mcts::Score
-        }
-    }
-}
-
+#[derive(Clone)]
 pub struct ThreatsAreFarEval;
 impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for ThreatsAreFarEval {
     fn evaluate(&self, state: &Spec::State) -> mcts::Score {
@@ -83,6 +68,7 @@ impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for ThreatsAreFarEva
     }
 }
 
+#[derive(Clone)]
 pub struct TicksSurvivedEval;
 impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for TicksSurvivedEval {
     fn evaluate(&self, state: &Spec::State) -> mcts::Score {
@@ -91,6 +77,7 @@ impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for TicksSurvivedEva
     }
 }
 
+#[derive(Clone)]
 pub struct NotDeadEval;
 impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for NotDeadEval {
     fn evaluate(&self, state: &Spec::State) -> mcts::Score {
@@ -101,6 +88,7 @@ impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for NotDeadEval {
     }
 }
 
+#[derive(Clone)]
 pub struct NotDeadShowOffEval;
 impl<Spec: mcts::MCTS<State = State>> mcts::Evaluator<Spec> for NotDeadShowOffEval {
     fn evaluate(&self, state: &Spec::State) -> mcts::Score {
@@ -136,6 +124,11 @@ mcts::SearchState<Spec> for State {
     fn apply_action(&mut self, action: Action) {
         self.simulate_tick(SimulationAction::Move { direction: action });
     }
+    fn determinize(&mut self, seed: u64) {
+        if self.is_stochastic() {
+            self.resample_stochastic_threats(seed);
+        }
+    }
 }
 
 
@@ -148,34 +141,218 @@ impl mcts::MCTS for MCTS {
     type Action = Action;
     type State = State;
     type ActionSpace = ActionSpace;
-    type Evaluator = TicksSurvivedEval;
-    type Budget = mcts::TimeBudget;
+    type Evaluator = grammar::Heuristic;
+    type Budget = mcts::TimeOrEvalsBudget;
+}
+
+/// What to do when the server state does not match our predictions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DesyncHandling {
+    /// Crash, to surface simulation bugs early.
+    Panic,
+    /// Report the differences, take the server state as the truth and keep
+    /// playing.
+    Resync,
 }
 
+/// How many recent observations to keep to infer hidden state on desyncs, or
+/// to identify threats of unknown style.
+const INFERENCE_WINDOW: usize = 30;
+/// How many moves a threat of unknown style must have done (in the inference
+/// window) before trying to identify its style.
+const MIN_MOVES_TO_IDENTIFY: usize = 5;
+/// Identifying styles or inferring unreliable threats again is expensive, only
+/// try every N ticks.
+const IDENTIFY_EVERY_N_TICKS: usize = 10;
+/// Inference runs before searching on the same tick, don't let it eat into the
+/// search budget too much.
+const INFERENCE_MAX_TIME: Duration = Duration::from_millis(15);
+
 pub struct Bot<'a> {
     pub algorithm: mcts::Algorithm<'a, MCTS>,
-    pub name: BotName,
+    pub spec: BotSpec,
+    pub desync_handling: DesyncHandling,
+    seed: u64,
+    /// Recent server states, to infer hidden state.
+    observations: VecDeque<Game>,
+    /// Styles that we identified for threats of unknown style, by index.
+    identified_styles: HashMap<usize, Style>,
 }
 
 impl Bot<'_> {
     pub fn new_best(state: State, seed: u64) -> Self {
-        Self::new(state, seed, BEST_BOT)
+        Self::new(state, seed, BotSpec::preset(BEST_BOT))
+    }
+
+    pub fn new(state: State, seed: u64, spec: BotSpec) -> Self {
+        Self {
+            algorithm: spec.make_algorithm(state, seed),
+            spec,
+            desync_handling: DesyncHandling::Panic,
+            seed,
+            observations: VecDeque::new(),
+            identified_styles: HashMap::new(),
+        }
     }
 
-    pub fn new(state: State, seed: u64, name: BotName) -> Self {
-        let params = Self::make_search_params(seed);
-        Self { algorithm: name.make_algorithm(state, params), name }
+    /// Replace the state we search from.
+    fn restart_search(&mut self, state: State) {
+        // Search internals (trees, best sequences) refer to the old state,
+        // start over.
+        self.algorithm = self.spec.make_algorithm(state, self.seed);
     }
 
-    fn make_search_params(seed: u64) -> mcts::SearchParams<MCTS> {
-        mcts::SearchParams::<MCTS>::new(
-            mcts::TimeBudget { max_time: std::time::Duration::from_millis(75) },
-            TicksSurvivedEval {}, seed)
+    /// Check our predictions against 'game', handling desyncs as configured,
+    /// and threats of unknown style.
+    fn update_state(&mut self, game: &Game) {
+        let mut game = game.clone();
+        if game.threats.len() != self.algorithm.state.threats.len() {
+            // Threat indices may not refer to the same threats anymore.
+            self.identified_styles.clear();
+            self.observations.clear();
+        }
+        for (&i, &style) in &self.identified_styles {
+            if let Some(threat) = game.threats.get_mut(i) {
+                threat.style = style;
+            }
+        }
+        if self.observations.len() == INFERENCE_WINDOW {
+            self.observations.pop_front();
+        }
+        self.observations.push_back(game.clone());
+        match self.desync_handling {
+            DesyncHandling::Panic => self.algorithm.state.verify_predictions(&game),
+            DesyncHandling::Resync => self.resync(&game),
+        }
+        self.algorithm.state.sync_stochastic_threats(&game);
+        if game.tick.is_multiple_of(IDENTIFY_EVERY_N_TICKS) {
+            self.reinfer_unreliable_threats();
+            self.identify_unknown_threats();
+        }
+    }
+
+    /// Take the server state as the truth if we mispredicted.
+    fn resync(&mut self, game: &Game) {
+        let mispredictions = self.algorithm.state.find_mispredictions(game);
+        if mispredictions.is_empty() { return; }
+        println!("[DESYNC] {} misprediction(s) on tick {}:",
+                 mispredictions.len(), self.algorithm.state.tick);
+        for misprediction in &mispredictions {
+            println!("[DESYNC]   {}", misprediction);
+        }
+        let options = self.inference_options();
+        let state = match inference::infer_state(
+            self.observations.make_contiguous(), &options) {
+            Ok(inference) => {
+                println!("[DESYNC] Inferred hidden state from {} observations (ambiguous: {})",
+                         self.observations.len(), inference.is_ambiguous());
+                inference.state
+            },
+            Err(e) => {
+                println!("[DESYNC] Could not infer hidden state ({:?}), keeping ours", e);
+                let mut state = self.algorithm.state.clone();
+                state.resync(game);
+                // We can't explain what these threats did, model them as
+                // random until we can infer them again (see
+                // 'reinfer_unreliable_threats').
+                let unreliable = mispredictions.iter().filter_map(|m| match m {
+                    Misprediction::ThreatPos { threat, .. }
+                    | Misprediction::ThreatDir { threat, .. }
+                    | Misprediction::ThreatPersonality { threat, .. } => Some(*threat),
+                    _ => None,
+                }).unique();
+                for threat in unreliable {
+                    println!("[DESYNC] Treating threat #{} as stochastic", threat);
+                    state.mark_unreliable(threat);
+                }
+                state
+            },
+        };
+        self.restart_search(state);
+    }
+
+    /// Try to infer the hidden state of threats whose moves we sample (see
+    /// 'resync'), to simulate them exactly again. Mispredictions don't check
+    /// those, so they would stay random otherwise.
+    fn reinfer_unreliable_threats(&mut self) {
+        let options = self.inference_options();
+        let observations = self.observations.make_contiguous();
+        let mut state = self.algorithm.state.clone();
+        let mut reinferred = false;
+        for (i, threat) in state.threats.iter_mut().enumerate() {
+            if !threat.unreliable || threat.style == Style::Unknown {
+                continue;
+            }
+            match inference::infer_threat(observations, i, &options) {
+                Ok(candidates) if candidates.len() == 1 => {
+                    println!("[DESYNC] Inferred hidden state of threat #{}, simulating it again", i);
+                    *threat = candidates[0].clone();
+                    reinferred = true;
+                },
+                Ok(candidates) => println!(
+                    "[DESYNC] Threat #{} still has {} possible hidden states", i, candidates.len()),
+                Err(e) => println!(
+                    "[DESYNC] Could not infer hidden state of threat #{} ({:?})", i, e),
+            }
+        }
+        if reinferred {
+            self.restart_search(state);
+        }
+    }
+
+    /// Try to find a known style that explains how threats of unknown style
+    /// moved recently, and simulate them as such from now on.
+    fn identify_unknown_threats(&mut self) {
+        let options = self.inference_options();
+        let observations = self.observations.make_contiguous();
+        let mut state = self.algorithm.state.clone();
+        let mut identified = false;
+        for (i, threat) in state.threats.iter_mut().enumerate() {
+            if threat.style != Style::Unknown ||
+                inference::count_moves(observations, i) < MIN_MOVES_TO_IDENTIFY {
+                continue;
+            }
+            let identification = inference::identify_style(observations, i, &options);
+            if !identification.unchecked.is_empty() {
+                println!("[IDENTIFY] Ran out of time checking if threat #{} is a {:?}",
+                         i, identification.unchecked);
+            }
+            if let Some(m) = inference::most_likely_style(&identification.matches) {
+                println!("[IDENTIFY] Threat #{} moves like a {:?} (likelihood {:.3})",
+                         i, m.style, m.likelihood);
+                if let [candidate] = m.candidates.as_slice() {
+                    *threat = candidate.clone();
+                } else {
+                    // Guessing among hidden states could mispredict later,
+                    // keep sampling its moves until we can tell them apart.
+                    println!("[IDENTIFY] {} possible hidden states, treating threat #{} as stochastic",
+                             m.candidates.len(), i);
+                    threat.style = m.style;
+                    threat.unreliable = true;
+                }
+                self.identified_styles.insert(i, m.style);
+                observations.iter_mut().for_each(|o| o.threats[i].style = m.style);
+                identified = true;
+            }
+        }
+        if identified {
+            self.restart_search(state);
+        }
+    }
+
+    /// Infer with our game rules and pathfinding, within a time budget.
+    fn inference_options(&self) -> inference::InferenceOptions {
+        let state = &self.algorithm.state;
+        inference::InferenceOptions {
+            game: state.options().clone(),
+            grid: Some(state.grid.clone()),
+            max_time: Some(INFERENCE_MAX_TIME),
+        }
     }
 
     /// Update state based on 'game', pick our next move, apply it locally.
     pub fn pick_move(&mut self, game: &Game) -> Option<Move> {
-        self.algorithm.state.verify_predictions(game);
+        self.update_state(game);
         let results = self.algorithm.search();
         println!("Search did {} evals, best score: {}",
                  results.stats.num_evals, results.stats.highest_score_seen);
@@ -193,13 +370,13 @@ impl Bot<'_> {
 
     /// Update state based on 'game', then apply given move.
     pub fn simulate(&mut self, game: &Game, direction: Option<Move>) {
-        self.algorithm.state.verify_predictions(game);
+        self.update_state(game);
         self.algorithm.state.simulate_tick(SimulationAction::Move { direction });
     }
 
     /// Update state based on 'game', then apply given MoveTo action.
     pub fn simulate_move_to(&mut self, game: &Game, position: &Pos) {
-        self.algorithm.state.verify_predictions(game);
+        self.update_state(game);
         self.algorithm.state.simulate_tick(SimulationAction::MoveTo { position: *position });
     }
 }
@@ -2,6 +2,9 @@ use std::sync::Arc;
 
 use itertools::chain;
 use once_cell::sync::Lazy;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 use crate::grid::{Grid, Move, Pos};
@@ -13,32 +16,147 @@ const MAX_TICKS: usize = 10000;
 // Max tick matching the server's. Once tick > this, stop.
 const GAME_END_TICKS: usize = 2000;
 
+// Points given per tick survived, matching the server's.
+const SCORE_PER_TICK: usize = 5;
+
 pub enum SimulationAction {
     Move { direction: Option<Move> },
     MoveTo { position: Pos },
 }
 
-/// Lookup of whether a given tick (index lookup) is a tick where threats move.
-static IS_MOVE_TICK: Lazy<Vec<bool>> = Lazy::new(|| {
+/// Lookup of whether a given tick (index lookup) is a tick where threats move,
+/// for the default tick speeds.
+static IS_MOVE_TICK: Lazy<Arc<Vec<bool>>> = Lazy::new(|| {
+    Arc::new(TickSpeedMap::default().move_ticks(MAX_TICKS))
+});
+
+/// Mood of a threat, which determines how often it moves. Changes over time.
+// Logic from:
+// https://github.com/JesseEmond/blitz-2025-registration/blob/e2472c198b9ebea2e88ca07d6df8759f11fcaf4b/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/threats/threat.decomp.js#L55-L79
+#[derive(EnumIter, Copy, Clone, Debug, PartialEq)]
+pub enum Personality {
+    Lazy,
+    Tease,
+    Awake,
+    Hungry,
+    Insane,
+}
+
+impl Personality {
+    /// Personality that a threat has when updated on a given (server) tick.
+    pub fn for_tick(tick: usize) -> Self {
+        match tick {
+            901.. => Personality::Insane,
+            701..=900 => Personality::Hungry,
+            501..=700 => Personality::Awake,
+            301..=500 => Personality::Tease,
+            0..=300 => Personality::Lazy,
+        }
+    }
+
+    /// Name used by the server.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Personality::Lazy => "lazy",
+            Personality::Tease => "tease",
+            Personality::Awake => "awake",
+            Personality::Hungry => "hungry",
+            Personality::Insane => "insane",
+        }
+    }
+
+    /// Personality from its server name, if known.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Personality::iter().find(|p| p.name() == name)
+    }
+}
+
+/// How many ticks a threat waits between moves, for each personality.
+/// Matches 'tickSpeedMap' in the JS by default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TickSpeedMap {
+    pub lazy: usize,
+    pub tease: usize,
+    pub awake: usize,
+    pub hungry: usize,
+    pub insane: usize,
+}
+
+impl Default for TickSpeedMap {
+    fn default() -> Self {
+        // See https://github.com/JesseEmond/blitz-2025-registration/blob/e2472c198b9ebea2e88ca07d6df8759f11fcaf4b/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/threats/threat.decomp.js#L450
+        Self { lazy: 5, tease: 4, awake: 3, hungry: 2, insane: 1 }
+    }
+}
+
+impl TickSpeedMap {
+    pub fn move_every_n_ticks(&self, personality: Personality) -> usize {
+        match personality {
+            Personality::Lazy => self.lazy,
+            Personality::Tease => self.tease,
+            Personality::Awake => self.awake,
+            Personality::Hungry => self.hungry,
+            Personality::Insane => self.insane,
+        }
+    }
+
+    /// Compute whether threats move on a given tick (index lookup), up to
+    /// 'max_tick' (inclusive).
     // Logic from:
     // https://github.com/JesseEmond/blitz-2025-registration/blob/6c705ae3a3ffcf806e28a5e3ba700a3b2a7f3ca8/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/threats/threat.decomp.js#L80-L88
     // Computed once so that we can look it up without keeping track of state.
-    let mut is_move_tick = Vec::new();
-    // Not entirely sure why despite reading the reversed code, but if we don't
-    // do this we are off-by-one.
-    is_move_tick.push(false);
-    let mut ticks_since_last_move = 0;
-    for tick in 0..=MAX_TICKS {
-        let mut do_move = false;
-        ticks_since_last_move += 1;
-        if ticks_since_last_move >= Threat::move_every_n_ticks(tick) {
-            ticks_since_last_move = 0;
-            do_move = true;
+    pub fn move_ticks(&self, max_tick: usize) -> Vec<bool> {
+        let mut is_move_tick = Vec::new();
+        // Not entirely sure why despite reading the reversed code, but if we don't
+        // do this we are off-by-one.
+        is_move_tick.push(false);
+        let mut ticks_since_last_move = 0;
+        for tick in 0..=max_tick {
+            let mut do_move = false;
+            ticks_since_last_move += 1;
+            let personality = Personality::for_tick(tick);
+            if ticks_since_last_move >= self.move_every_n_ticks(personality) {
+                ticks_since_last_move = 0;
+                do_move = true;
+            }
+            is_move_tick.push(do_move);
         }
-        is_move_tick.push(do_move);
+        is_move_tick
     }
-    is_move_tick
-});
+}
+
+/// Distribution over a threat's available moves, for threats that we can't
+/// simulate exactly (unknown style or unreliable predictions). Moves are picked
+/// with probabilities proportional to these weights, relative to the direction
+/// the threat is facing. Defaults to uniform, like a Goldfish.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveDistribution {
+    /// Weight of continuing in the same direction.
+    pub forward: f64,
+    /// Weight of turning left or right.
+    pub turn: f64,
+    /// Weight of turning back.
+    pub back: f64,
+}
+
+impl Default for MoveDistribution {
+    fn default() -> Self {
+        Self { forward: 1.0, turn: 1.0, back: 1.0 }
+    }
+}
+
+impl MoveDistribution {
+    /// Relative weight of doing 'm' when facing 'facing'.
+    pub fn weight(&self, facing: Move, m: Move) -> f64 {
+        if m == facing {
+            self.forward
+        } else if m == facing.opposite() {
+            self.back
+        } else {
+            self.turn
+        }
+    }
+}
 
 // Styles map to internal names in the JS here:
 // https://github.com/JesseEmond/blitz-2025-registration/blob/7afcfb849b990caa69cee0f83ae96aae6f49740f/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/threats/threat.decomp.js#L452C80-L452C88
@@ -56,10 +174,38 @@ pub enum Style {
     Deer,
     /// Aka "sheriff"
     Hawk,
+    /// Style that we do not know how to simulate (e.g. new server style). We
+    /// assume it wanders randomly until we can identify it from its moves.
+    Unknown,
+}
+
+impl Style {
+    /// Styles that we know how to simulate exactly.
+    pub fn known() -> impl Iterator<Item = Style> {
+        Style::iter().filter(|&s| s != Style::Unknown)
+    }
+
+    /// Name used by the server.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Style::Goldfish => "goldfish",
+            Style::Bull => "bull",
+            Style::Shark => "shark",
+            Style::Owl => "owl",
+            Style::Deer => "deer",
+            Style::Hawk => "hawk",
+            Style::Unknown => "unknown",
+        }
+    }
+
+    /// Style from its server name, if we know how to simulate it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Style::known().find(|s| s.name() == name)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
-enum ThreatStorage {
+pub(crate) enum ThreatStorage {
     Owl { target_last_seen: Pos },
     Hawk {
         target_last_seen: Option<Pos>,
@@ -74,16 +220,23 @@ pub struct Threat {
     /// Direction the threat is facing.
     pub dir: Move,
     pub style: Style,
-    spawn: Pos,
-    seed: usize,
+    /// Current mood of the threat, see 'Personality'.
+    pub personality: Personality,
+    pub(crate) spawn: Pos,
+    pub(crate) seed: usize,
     /// Used by some threat styles to remember things.
-    storage: Option<ThreatStorage>,
+    pub(crate) storage: Option<ThreatStorage>,
+    /// If we can't trust our simulation of this threat (e.g. repeated
+    /// mispredictions), and should treat its moves as random instead.
+    pub(crate) unreliable: bool,
 }
 
 impl Threat {
     pub fn spawn(pos: Pos, style: Style) -> Self {
         let mut t = Threat {
             pos, style, spawn: pos.clone(), seed: 0, storage: None,
+            unreliable: false,
+            personality: Personality::Lazy,
             // Put a temporary value before generating it
             dir: Move::Up
         };
@@ -91,11 +244,26 @@ impl Threat {
         t
     }
 
+    /// Whether this threat's moves are sampled from a 'MoveDistribution'
+    /// instead of following the logic of its style.
+    pub fn is_stochastic(&self) -> bool {
+        self.style == Style::Unknown || self.unreliable
+    }
+
     /// Returns whether we know how to simulate this threat.
-    fn simulate(&mut self, tick: usize, player: &Pos, player_prev: &Pos,
-                grid: &PathfindingGrid) {
-        if !Self::moves_on_tick(tick) { return; }
-        if let Some(m) = self.next_move(tick, player, player_prev, grid) {
+    pub(crate) fn simulate(&mut self, tick: usize, is_move_tick: bool,
+                           player: &Pos, player_prev: &Pos,
+                           grid: &PathfindingGrid, moves: &MoveDistribution) {
+        // Same off-by-one as in 'TickSpeedMap::move_ticks' and owls, the server
+        // sees the previous tick.
+        self.personality = Personality::for_tick(tick.saturating_sub(1));
+        if !is_move_tick { return; }
+        let next_move = if self.is_stochastic() {
+            Some(self.sample_move(moves, &grid.grid))
+        } else {
+            self.next_move(tick, player, player_prev, grid)
+        };
+        if let Some(m) = next_move {
             self.pos = self.pos.moved(m);
             self.dir = m;
         }
@@ -104,6 +272,7 @@ impl Threat {
     fn next_move(&mut self, tick: usize, player: &Pos, player_prev: &Pos,
                  grid: &PathfindingGrid) -> Option<Move> {
         match self.style {
+            Style::Unknown => unreachable!("Unknown styles are stochastic"),
             Style::Goldfish => {
                 // See girouette.js
                 let rand = self._next_rand();
@@ -143,6 +312,12 @@ impl Threat {
             },
             Style::Shark => {
                 // See aggressive.js
+                if !grid.grid.is_empty(player_prev) {
+                    // Player was not seen yet (initial '(-1, -1)'), which only
+                    // happens if threats move on the very first tick (e.g.
+                    // custom tick speeds). No path to follow.
+                    return None;
+                }
                 grid.get_aggressive_next_move(&self.pos, &player_prev)
             },
             Style::Owl => {
@@ -216,6 +391,26 @@ impl Threat {
         }
     }
 
+    /// Pick a move following 'moves', using our seed as the source of
+    /// randomness. Re-seeding the threat samples a different future.
+    fn sample_move(&mut self, moves: &MoveDistribution, grid: &Grid) -> Move {
+        let rand = self._next_rand();
+        let directions = grid.available_moves(&self.pos);
+        let weights: Vec<f64> = directions.iter()
+            .map(|&d| moves.weight(self.dir, d).max(0.0)).collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            let idx = (rand * directions.len() as f64).floor();
+            return directions[idx as usize];
+        }
+        let mut target = rand * total;
+        for (&d, &weight) in directions.iter().zip(weights.iter()) {
+            if target < weight { return d; }
+            target -= weight;
+        }
+        *directions.last().unwrap()
+    }
+
     fn get_random_intersection(&mut self, grid: &Grid) -> Pos {
         assert!(!grid.best_intersections.is_empty());
         let o = self._next_rand() * grid.best_intersections.len() as f64;
@@ -230,21 +425,6 @@ impl Threat {
         [Move::Up, Move::Down, Move::Left, Move::Right][idx]
     }
 
-    fn move_every_n_ticks(tick: usize) -> usize {
-        // See https://github.com/JesseEmond/blitz-2025-registration/blob/e2472c198b9ebea2e88ca07d6df8759f11fcaf4b/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/threats/threat.decomp.js#L55-L79
-        match tick {
-            901.. => 1,
-            701..=900 => 2,
-            501..=700 => 3,
-            301..=500 => 4,
-            0..=300 => 5,
-        }
-    }
-
-    pub fn moves_on_tick(tick: usize) -> bool {
-        IS_MOVE_TICK[tick]
-    }
-
     fn get_possible_directions<'a>(&'a self, grid: &'a Grid) -> &Vec<Move> {
         // Grid precomputed moves are created following getPossibleDirections
         grid.available_moves(&self.pos)
@@ -267,15 +447,80 @@ pub enum GameOverCheck {
     StartOfTickAndAfterPlayerMove,
 }
 
-/// Game options to customize the game's logic.
+/// Game options to customize the game's logic. Defaults match the server's
+/// rules.
 #[derive(Clone)]
 pub struct GameOptions {
-    /// How to check for the end of a game.
+    /// How to check for the end of a game. Also decides if the player can
+    /// "jump over" threats.
     pub game_over: GameOverCheck,
+    /// Last tick of the game. Once the tick is past this, the player won.
+    pub end_tick: usize,
+    /// Points given for every tick survived.
+    pub score_per_tick: usize,
+    /// How often threats move, per personality.
+    pub tick_speeds: TickSpeedMap,
+    /// If set, overrides the tick that the game starts at.
+    pub starting_tick: Option<usize>,
+    /// How threats that we can't simulate exactly move.
+    pub stochastic_moves: MoveDistribution,
 }
 impl GameOptions {
     pub fn default() -> Self {
-        Self { game_over: GameOverCheck::StartOfTick }
+        Self {
+            game_over: GameOverCheck::StartOfTick,
+            end_tick: GAME_END_TICKS,
+            score_per_tick: SCORE_PER_TICK,
+            tick_speeds: TickSpeedMap::default(),
+            starting_tick: None,
+            stochastic_moves: MoveDistribution::default(),
+        }
+    }
+}
+
+/// Difference between what we predicted and what the server sent back.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Misprediction {
+    Tick { expected: usize, actual: usize },
+    PlayerPos { expected: Pos, actual: Pos },
+    Alive { expected: bool, actual: bool },
+    ThreatCount { expected: usize, actual: usize },
+    /// Index of the threat, with the predicted and observed values.
+    ThreatStyle { threat: usize, expected: Style, actual: Style },
+    ThreatPos { threat: usize, style: Style, expected: Pos, actual: Pos },
+    ThreatDir { threat: usize, style: Style, expected: Move, actual: Move },
+    ThreatPersonality {
+        threat: usize,
+        style: Style,
+        expected: Personality,
+        actual: Personality,
+    },
+}
+
+impl std::fmt::Display for Misprediction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tick { expected, actual } =>
+                write!(f, "tick: expected {}, got {}", expected, actual),
+            Self::PlayerPos { expected, actual } =>
+                write!(f, "player pos: expected {:?}, got {:?}", expected, actual),
+            Self::Alive { expected, actual } =>
+                write!(f, "alive: expected {}, got {}", expected, actual),
+            Self::ThreatCount { expected, actual } =>
+                write!(f, "# threats: expected {}, got {}", expected, actual),
+            Self::ThreatStyle { threat, expected, actual } =>
+                write!(f, "threat #{} style: expected {:?}, got {:?}", threat,
+                       expected, actual),
+            Self::ThreatPos { threat, style, expected, actual } =>
+                write!(f, "threat #{} ({:?}) pos: expected {:?}, got {:?}",
+                       threat, style, expected, actual),
+            Self::ThreatDir { threat, style, expected, actual } =>
+                write!(f, "threat #{} ({:?}) dir: expected {:?}, got {:?}",
+                       threat, style, expected, actual),
+            Self::ThreatPersonality { threat, style, expected, actual } =>
+                write!(f, "threat #{} ({:?}) personality: expected {:?}, got {:?}",
+                       threat, style, expected, actual),
+        }
     }
 }
 
@@ -292,9 +537,11 @@ pub struct Game {
 pub struct State {
     pub grid: Arc<PathfindingGrid>,
     options: GameOptions,
+    /// Lookup of whether a given tick is a tick where threats move.
+    move_ticks: Arc<Vec<bool>>,
     pub tick: usize,
     pub pos: Pos,
-    prev_pos: Pos,
+    pub(crate) prev_pos: Pos,
     pub threats: Vec<Threat>,
     pub game_over: bool,
 }
@@ -305,17 +552,32 @@ impl State {
     }
 
     pub fn new_custom(game: Game, options: GameOptions) -> Self {
+        let grid = Arc::new(PathfindingGrid::new(game.grid.clone()));
+        Self::with_grid(game, grid, options)
+    }
+
+    /// Like 'new_custom', reusing pathfinding already computed for 'game.grid'.
+    pub fn with_grid(game: Game, grid: Arc<PathfindingGrid>, options: GameOptions) -> Self {
         // Matches
         // https://github.com/JesseEmond/blitz-2025-registration/blob/dbe84ed80ebc441d071d5e6eb0d6a476d580a9e2/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/threats/threat.decomp.js#L467
         let prev_pos = Pos { x: -1, y: -1 };
+        // +1 to be able to look up ticks after the game is won.
+        let max_tick = options.end_tick + 1;
+        let move_ticks = if options.tick_speeds == TickSpeedMap::default()
+            && max_tick <= MAX_TICKS {
+            IS_MOVE_TICK.clone()
+        } else {
+            Arc::new(options.tick_speeds.move_ticks(max_tick))
+        };
         State {
-            grid: Arc::new(PathfindingGrid::new(game.grid)),
-            tick: game.tick,
+            grid,
+            tick: options.starting_tick.unwrap_or(game.tick),
             pos: game.pos,
             prev_pos,
             threats: game.threats.clone(),
             game_over: !game.alive,
             options,
+            move_ticks,
         }
     }
 
@@ -350,8 +612,10 @@ impl State {
             self.game_over = self.check_game_over();
             if self.game_over { return; }
         }
+        let is_move_tick = self.moves_on_tick(self.tick);
         for t in &mut self.threats {
-            t.simulate(self.tick, &self.pos, &self.prev_pos, &self.grid);
+            t.simulate(self.tick, is_move_tick, &self.pos, &self.prev_pos,
+                       &self.grid, &self.options.stochastic_moves);
         }
         // Some threats only see the character position from the prev tick, see
         // https://github.com/JesseEmond/blitz-2025-registration/blob/dbe84ed80ebc441d071d5e6eb0d6a476d580a9e2/disassembled_js/490a918d96484178d4b23d814405ac87/challenge/world.decomp.js#L206-L208
@@ -363,15 +627,133 @@ impl State {
     pub fn verify_predictions(&self, game: &Game) {
         println!("Tick: {}", self.tick);
         println!("Player: {:?}", self.pos);
-        assert_eq!(self.tick, game.tick, "tick");
-        assert_eq!(self.pos, game.pos, "pos");
-        assert_eq!(self.game_over, !game.alive, "alive");
-        self.threats.iter().zip(game.threats.iter()).for_each(|(threat, actual)| {
-            assert_eq!(threat.style, actual.style);
-            assert_eq!(threat.dir, actual.dir, "{:?} dir (@{:?})",
-                       threat.style, actual.pos);
-            assert_eq!(threat.pos, actual.pos, "{:?} pos", threat.style);
-        });
+        if let Some(misprediction) = self.find_mispredictions(game).first() {
+            panic!("Misprediction on tick {}: {}", self.tick, misprediction);
+        }
+    }
+
+    /// Compare our predicted state to what the server sent back.
+    pub fn find_mispredictions(&self, game: &Game) -> Vec<Misprediction> {
+        let mut diffs = Vec::new();
+        if self.tick != game.tick {
+            diffs.push(Misprediction::Tick { expected: self.tick, actual: game.tick });
+        }
+        if self.pos != game.pos {
+            diffs.push(Misprediction::PlayerPos { expected: self.pos, actual: game.pos });
+        }
+        if self.game_over == game.alive {
+            diffs.push(Misprediction::Alive {
+                expected: !self.game_over, actual: game.alive });
+        }
+        if self.threats.len() != game.threats.len() {
+            diffs.push(Misprediction::ThreatCount {
+                expected: self.threats.len(), actual: game.threats.len() });
+        }
+        for (i, (threat, actual)) in self.threats.iter().zip(game.threats.iter()).enumerate() {
+            let style = threat.style;
+            if style != actual.style {
+                diffs.push(Misprediction::ThreatStyle {
+                    threat: i, expected: style, actual: actual.style });
+                // Other fields are meaningless if this is a different threat.
+                continue;
+            }
+            if threat.is_stochastic() {
+                // Can't predict those, see 'sync_stochastic_threats'.
+                continue;
+            }
+            if threat.pos != actual.pos {
+                diffs.push(Misprediction::ThreatPos {
+                    threat: i, style, expected: threat.pos, actual: actual.pos });
+            }
+            if threat.dir != actual.dir {
+                diffs.push(Misprediction::ThreatDir {
+                    threat: i, style, expected: threat.dir, actual: actual.dir });
+            }
+            if threat.personality != actual.personality {
+                diffs.push(Misprediction::ThreatPersonality {
+                    threat: i, style, expected: threat.personality,
+                    actual: actual.personality });
+            }
+        }
+        diffs
+    }
+
+    /// Overwrite our state with what the server sent back, after a
+    /// misprediction. Hidden threat state (RNG seed, memory) cannot be observed,
+    /// so we keep our predicted one where the threat still matches, as a best
+    /// effort.
+    pub fn resync(&mut self, game: &Game) {
+        self.tick = game.tick;
+        if self.pos != game.pos {
+            // We can't know where the player was before, assume it was here.
+            self.prev_pos = game.pos;
+        }
+        self.pos = game.pos;
+        self.game_over = !game.alive;
+        let predicted = std::mem::take(&mut self.threats);
+        self.threats = game.threats.iter().enumerate().map(|(i, actual)| {
+            match predicted.get(i) {
+                Some(threat) if threat.style == actual.style => {
+                    let mut threat = threat.clone();
+                    threat.pos = actual.pos;
+                    threat.dir = actual.dir;
+                    threat.personality = actual.personality;
+                    threat
+                },
+                _ => actual.clone(),
+            }
+        }).collect();
+    }
+
+    /// Take the observed positions of threats that we can't simulate exactly.
+    pub fn sync_stochastic_threats(&mut self, game: &Game) {
+        for (threat, actual) in self.threats.iter_mut().zip(game.threats.iter()) {
+            if threat.is_stochastic() && threat.style == actual.style {
+                threat.pos = actual.pos;
+                threat.dir = actual.dir;
+                threat.personality = actual.personality;
+            }
+        }
+    }
+
+    /// Stop trusting our simulation of a threat, sample its moves instead.
+    pub fn mark_unreliable(&mut self, threat: usize) {
+        self.threats[threat].unreliable = true;
+    }
+
+    /// Whether some threats move randomly, i.e. simulating is not
+    /// deterministic without 'resample_stochastic_threats'.
+    pub fn is_stochastic(&self) -> bool {
+        self.threats.iter().any(|t| t.is_stochastic())
+    }
+
+    /// Sample a new random future for threats that we can't simulate exactly,
+    /// by re-seeding them.
+    pub fn resample_stochastic_threats(&mut self, seed: u64) {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for threat in self.threats.iter_mut().filter(|t| t.is_stochastic()) {
+            threat.seed = rng.gen::<u32>() as usize;
+        }
+    }
+
+    pub fn options(&self) -> &GameOptions {
+        &self.options
+    }
+
+    /// Snapshot of the observable parts of the state.
+    pub fn to_game(&self) -> Game {
+        Game {
+            tick: self.tick,
+            pos: self.pos,
+            grid: self.grid.grid.clone(),
+            threats: self.threats.clone(),
+            alive: !self.game_over,
+        }
+    }
+
+    /// Whether threats move when simulating the given tick.
+    pub fn moves_on_tick(&self, tick: usize) -> bool {
+        self.move_ticks[tick]
     }
 
     pub fn check_game_over(&self) -> bool {
@@ -382,11 +764,11 @@ impl State {
     }
 
     pub fn player_won(&self) -> bool {
-        self.tick > GAME_END_TICKS
+        self.tick > self.options.end_tick
     }
     
     pub fn score(&self) -> usize {
-        self.tick * 5
+        self.tick * self.options.score_per_tick
     }
 }
 
@@ -394,6 +776,7 @@ impl State {
 mod tests {
     use super::*;
     use super::super::grid::{make_grid};
+    use itertools::Itertools;
 
     #[test]
     fn test_owl_prediction_misprediction_repro() {
@@ -426,4 +809,108 @@ mod tests {
         assert_eq!(threat.next_move(tick, &player, &prev_player, &grid),
                    Some(Move::Up));
     }
+
+    #[test]
+    fn test_default_tick_speeds_move_schedule() {
+        let move_ticks = TickSpeedMap::default().move_ticks(MAX_TICKS);
+        // Lazy: every 5 ticks, with our off-by-one.
+        assert_eq!(&move_ticks[..11], &[false, false, false, false, false, true,
+                                        false, false, false, false, true]);
+        // Insane: every tick.
+        assert!(move_ticks[1000..1100].iter().all(|&m| m));
+        assert_eq!(Personality::for_tick(300), Personality::Lazy);
+        assert_eq!(Personality::for_tick(301), Personality::Tease);
+        assert_eq!(Personality::for_tick(901), Personality::Insane);
+    }
+
+    #[test]
+    fn test_resync_after_misprediction() {
+        let grid = make_grid(vec![
+            "#######",
+            "#     #",
+            "# # # #",
+            "#     #",
+            "#######",
+        ]);
+        let threats = vec![
+            Threat::spawn(Pos { x: 1, y: 1 }, Style::Goldfish),
+            Threat::spawn(Pos { x: 5, y: 3 }, Style::Hawk),
+        ];
+        let game = Game {
+            tick: 1, pos: Pos { x: 3, y: 3 }, grid, threats, alive: true };
+        let mut state = State::new(game.clone());
+        assert!(state.find_mispredictions(&game).is_empty());
+
+        let mut observed = game.clone();
+        observed.threats[1].pos = Pos { x: 5, y: 2 };
+        observed.threats[1].dir = game.threats[1].dir.opposite();
+        assert_eq!(state.find_mispredictions(&observed), vec![
+            Misprediction::ThreatPos {
+                threat: 1, style: Style::Hawk, expected: Pos { x: 5, y: 3 },
+                actual: Pos { x: 5, y: 2 } },
+            Misprediction::ThreatDir {
+                threat: 1, style: Style::Hawk, expected: game.threats[1].dir,
+                actual: game.threats[1].dir.opposite() },
+        ]);
+        state.resync(&observed);
+        assert!(state.find_mispredictions(&observed).is_empty());
+        // Hidden state is kept from our predictions.
+        assert_eq!(state.threats[1].seed, game.threats[1].seed);
+    }
+
+    #[test]
+    fn test_stochastic_threats() {
+        let grid = make_grid(vec![
+            "#######",
+            "#     #",
+            "# # # #",
+            "#     #",
+            "#######",
+        ]);
+        let threats = vec![
+            Threat::spawn(Pos { x: 1, y: 1 }, Style::Unknown),
+            Threat::spawn(Pos { x: 3, y: 2 }, Style::Hawk),
+        ];
+        // Far enough that the unknown threat can't reach us in 3 moves.
+        let game = Game {
+            tick: 1, pos: Pos { x: 5, y: 3 }, grid, threats, alive: true };
+        let mut options = GameOptions::default();
+        options.tick_speeds = TickSpeedMap {
+            lazy: 1, tease: 1, awake: 1, hungry: 1, insane: 1 };
+        // Never turn back, so the unknown threat goes around the loop.
+        options.stochastic_moves = MoveDistribution {
+            forward: 1.0, turn: 1.0, back: 0.0 };
+        let mut state = State::new_custom(game.clone(), options);
+        state.threats[0].dir = Move::Right;
+        assert!(state.is_stochastic());
+
+        let futures: Vec<Vec<Pos>> = (0..10).map(|seed| {
+            let mut state = state.clone();
+            state.resample_stochastic_threats(seed);
+            (0..3).map(|_| {
+                state.simulate_tick(SimulationAction::Move { direction: None });
+                state.threats[0].pos
+            }).collect()
+        }).collect();
+        for future in &futures {
+            let path: Vec<Pos> = std::iter::once(state.threats[0].pos)
+                .chain(future.iter().cloned()).collect();
+            for (a, b, c) in path.iter().tuple_windows() {
+                assert_eq!(a.manhattan_dist(b), 1);
+                assert_eq!(b.manhattan_dist(c), 1);
+                assert_ne!(a, c, "turned back");
+            }
+        }
+        assert!(futures.iter().any(|f| f != &futures[0]),
+                "resampling should give different futures");
+
+        // Unreliable threats are not mispredictions, we take their position.
+        let mut observed = game.clone();
+        observed.threats[1].pos = Pos { x: 3, y: 1 };
+        assert!(!state.find_mispredictions(&observed).is_empty());
+        state.mark_unreliable(1);
+        assert!(state.find_mispredictions(&observed).is_empty());
+        state.sync_stochastic_threats(&observed);
+        assert_eq!(state.threats[1].pos, Pos { x: 3, y: 1 });
+    }
 }
@@ -0,0 +1,276 @@
+/// Interactive terminal viewer, to step through games tick by tick and see
+/// what the bot was thinking.
+
+use std::io::{self, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::Print;
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+
+use crate::grid::{Move, Pos};
+use crate::mcts::{self, Score};
+use crate::search::{Action, MCTS};
+use crate::simulation::{State, Style, ThreatStorage};
+
+/// Ticks skipped by PageUp/PageDown.
+const PAGE_TICKS: usize = 10;
+
+/// What the search found on a tick.
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    /// Best sequence found, starting with the action played.
+    pub best_sequence: Vec<Action>,
+    /// (action, visits, average score) of each action considered.
+    pub action_stats: Vec<(Action, usize, Score)>,
+    pub num_evals: usize,
+}
+
+impl SearchInfo {
+    pub fn from_results(results: &mcts::Results<MCTS>) -> Self {
+        Self {
+            best_sequence: results.best_sequence.clone(),
+            action_stats: results.action_stats.iter()
+                .map(|stats| (stats.action, stats.visits, stats.avg_score))
+                .collect(),
+            num_evals: results.stats.num_evals,
+        }
+    }
+}
+
+/// A tick of the game to show.
+#[derive(Clone)]
+pub struct ViewerFrame {
+    pub state: State,
+    /// Search done on this tick, if we played the game ourselves.
+    pub search: Option<SearchInfo>,
+    /// Anything else worth showing (e.g. mispredictions in replays).
+    pub notes: Vec<String>,
+}
+
+impl ViewerFrame {
+    pub fn new(state: State) -> Self {
+        Self { state, search: None, notes: Vec::new() }
+    }
+}
+
+/// Draw the grid of a frame as text, one string per row: '#' for walls, '@'
+/// for the player, the first letter of their style for threats ('?' if
+/// unknown), '.' along the best sequence found, and optionally 'x' where
+/// Owls and Hawks are headed.
+pub fn text_grid(frame: &ViewerFrame, show_targets: bool) -> Vec<String> {
+    let state = &frame.state;
+    let grid = &state.grid.grid;
+    let (width, height) = grid.dims();
+    let mut rows: Vec<Vec<char>> = (0..height as i16).map(|y| {
+        (0..width as i16)
+            .map(|x| if grid.is_empty(&Pos { x, y }) { ' ' } else { '#' })
+            .collect()
+    }).collect();
+    let mut set = |pos: &Pos, c: char| {
+        if grid.on_grid(pos) {
+            rows[pos.y as usize][pos.x as usize] = c;
+        }
+    };
+    if let Some(search) = &frame.search {
+        let mut pos = state.pos;
+        for direction in search.best_sequence.iter().flatten() {
+            let next = pos.moved(*direction);
+            if !grid.is_empty(&next) { continue; }
+            pos = next;
+            set(&pos, '.');
+        }
+    }
+    if show_targets {
+        for threat in &state.threats {
+            if let Some(target) = target_of(threat.storage.as_ref()) {
+                set(&target, 'x');
+            }
+        }
+    }
+    for threat in &state.threats {
+        set(&threat.pos, style_char(threat.style));
+    }
+    set(&state.pos, '@');
+    rows.into_iter().map(|row| row.into_iter().collect()).collect()
+}
+
+/// Text shown next to the grid: tick info, threats, and search results.
+pub fn text_panel(frame: &ViewerFrame) -> Vec<String> {
+    let state = &frame.state;
+    let mut lines = vec![
+        format!("Tick {}  pos ({}, {})  score {}{}", state.tick, state.pos.x,
+                state.pos.y, state.score(),
+                if state.game_over { "  GAME OVER" } else { "" }),
+        String::new(),
+        "Threats:".to_string(),
+    ];
+    for (i, threat) in state.threats.iter().enumerate() {
+        lines.push(format!(
+            " {} #{:<2} {:<8} ({:>2}, {:>2}) {:<5} {:<6} {}{}",
+            style_char(threat.style), i, threat.style.name(), threat.pos.x,
+            threat.pos.y, move_name(Some(threat.dir)), threat.personality.name(),
+            storage_text(threat.storage.as_ref()),
+            if threat.unreliable { " (unreliable)" } else { "" }));
+    }
+    if let Some(search) = &frame.search {
+        lines.push(String::new());
+        lines.push(format!("Search ({} evals), best sequence:", search.num_evals));
+        lines.push(format!(" {}", search.best_sequence.iter()
+            .map(|action| move_name(*action)).collect::<Vec<_>>().join(" ")));
+        if !search.action_stats.is_empty() {
+            lines.push("Actions:".to_string());
+            for (action, visits, score) in &search.action_stats {
+                lines.push(format!(" {:<5} visits {:>6}  avg score {:>8.1}",
+                                   move_name(*action), visits, score));
+            }
+        }
+    }
+    if !frame.notes.is_empty() {
+        lines.push(String::new());
+        lines.extend(frame.notes.iter().cloned());
+    }
+    lines
+}
+
+/// Step through frames interactively until the user quits.
+pub fn run(name: &str, frames: &[ViewerFrame]) -> io::Result<()> {
+    if frames.is_empty() {
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+    let result = view_loop(&mut stdout, name, frames);
+    execute!(stdout, cursor::Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn view_loop(stdout: &mut io::Stdout, name: &str, frames: &[ViewerFrame]) -> io::Result<()> {
+    let last = frames.len() - 1;
+    let mut index = 0;
+    let mut show_targets = false;
+    loop {
+        draw(stdout, name, &frames[index], index, frames.len(), show_targets)?;
+        let Event::Key(KeyEvent { code, kind: KeyEventKind::Press, .. }) = event::read()? else {
+            continue;
+        };
+        index = match code {
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char(' ') => (index + 1).min(last),
+            KeyCode::Left | KeyCode::Char('h') => index.saturating_sub(1),
+            KeyCode::PageDown => (index + PAGE_TICKS).min(last),
+            KeyCode::PageUp => index.saturating_sub(PAGE_TICKS),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            KeyCode::Char('t') => {
+                show_targets = !show_targets;
+                index
+            },
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => index,
+        };
+    }
+}
+
+fn draw(stdout: &mut io::Stdout, name: &str, frame: &ViewerFrame, index: usize,
+        num_frames: usize, show_targets: bool) -> io::Result<()> {
+    queue!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+    let header = format!(
+        "{}  [{}/{}]  <-/-> step, PgUp/PgDn +-{}, Home/End, t: targets ({}), q: quit",
+        name, index + 1, num_frames, PAGE_TICKS, if show_targets { "on" } else { "off" });
+    let grid = text_grid(frame, show_targets);
+    let panel = text_panel(frame);
+    let grid_width = grid.first().map_or(0, |row| row.chars().count());
+    let lines = std::iter::once(header).chain(std::iter::once(String::new()))
+        .chain((0..grid.len().max(panel.len())).map(|i| {
+            format!("{:<width$}  {}",
+                    grid.get(i).map_or("", |row| row.as_str()),
+                    panel.get(i).map_or("", |line| line.as_str()),
+                    width = grid_width)
+        }));
+    for (y, line) in lines.enumerate() {
+        // Raw mode doesn't translate newlines, position each line explicitly.
+        queue!(stdout, cursor::MoveTo(0, y as u16), Print(line))?;
+    }
+    stdout.flush()
+}
+
+fn target_of(storage: Option<&ThreatStorage>) -> Option<Pos> {
+    match storage {
+        Some(ThreatStorage::Owl { target_last_seen }) => Some(*target_last_seen),
+        Some(ThreatStorage::Hawk { target_last_seen, idle_position, .. }) =>
+            target_last_seen.or(*idle_position),
+        None => None,
+    }
+}
+
+fn storage_text(storage: Option<&ThreatStorage>) -> String {
+    let pos_text = |pos: &Option<Pos>| pos.map_or("-".to_string(),
+                                                   |p| format!("({}, {})", p.x, p.y));
+    match storage {
+        Some(ThreatStorage::Owl { target_last_seen }) =>
+            format!("target {}", pos_text(&Some(*target_last_seen))),
+        Some(ThreatStorage::Hawk { target_last_seen, idle_position, idle_rounds }) =>
+            format!("target {} idle {} ({} rounds)", pos_text(target_last_seen),
+                    pos_text(idle_position), idle_rounds),
+        None => String::new(),
+    }
+}
+
+fn style_char(style: Style) -> char {
+    match style {
+        Style::Unknown => '?',
+        _ => style.name().chars().next().unwrap().to_ascii_uppercase(),
+    }
+}
+
+fn move_name(action: Action) -> &'static str {
+    match action {
+        Some(Move::Up) => "up",
+        Some(Move::Down) => "down",
+        Some(Move::Left) => "left",
+        Some(Move::Right) => "right",
+        None => "stay",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::make_grid;
+    use crate::simulation::{Game, Threat};
+
+    #[test]
+    fn test_text_grid() {
+        let grid = make_grid(vec![
+            "######",
+            "#    #",
+            "#    #",
+            "######",
+        ]);
+        let threats = vec![Threat::spawn(Pos { x: 4, y: 2 }, Style::Bull)];
+        let state = State::new(Game { tick: 1, pos: Pos { x: 1, y: 1 }, grid, threats,
+                                      alive: true });
+        let mut frame = ViewerFrame::new(state);
+        assert_eq!(text_grid(&frame, false), vec![
+            "######",
+            "#@   #",
+            "#   B#",
+            "######",
+        ]);
+        // Moves into walls are ignored by the simulation, and so by the path.
+        frame.search = Some(SearchInfo {
+            best_sequence: vec![Some(Move::Right), Some(Move::Up), Some(Move::Down), None],
+            action_stats: vec![(Some(Move::Right), 10, 1.0)],
+            num_evals: 10,
+        });
+        assert_eq!(text_grid(&frame, false), vec![
+            "######",
+            "#@.  #",
+            "# . B#",
+            "######",
+        ]);
+        assert!(text_panel(&frame).iter().any(|line| line.contains("right up down stay")));
+    }
+}